@@ -0,0 +1,102 @@
+use crate::{
+    error::Error,
+    sweep::{Board, SafeZone, Settings},
+};
+use std::{
+    fmt,
+    hint::black_box,
+    time::{Duration, Instant},
+};
+
+/// The number of times to check for a win, which is too quick to time once.
+const WON_CALLS: u32 = 1_000_000;
+
+/// How long the core operations of a [`Board`] took on a square board.
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct Timings {
+    pub(crate) tiles: usize,
+    pub(crate) mines: usize,
+    /// Creating the board.
+    pub(crate) new: Duration,
+    /// The first expose, which places the mines and cascades.
+    pub(crate) expose: Duration,
+    /// The number of tiles the first expose uncovered.
+    pub(crate) exposed: usize,
+    /// A single check for a win.
+    pub(crate) won: Duration,
+    /// Exposing a mine, which uncovers the whole board.
+    pub(crate) lose: Duration,
+}
+
+/// Time creating, exposing, checking and losing a square board of about `tiles` tiles, a
+/// `density` of which are mines.
+pub(crate) fn bench(tiles: usize, density: f64, seed: u64) -> Result<Timings, Error> {
+    let side = (tiles as f64).sqrt().round() as usize;
+    let tiles = side * side;
+    let mines = (tiles as f64 * density) as usize;
+
+    let started = Instant::now();
+    let mut board = Board::new(
+        Settings::builder()
+            .rows(side)
+            .columns(side)
+            .mines(mines.min(tiles.saturating_sub(1)))
+            // open a cascade, which is the slow part of exposing
+            .safe_zone(SafeZone::Neighbourhood)
+            .seed(seed)
+            .build(),
+    )?;
+    let new = started.elapsed();
+
+    let started = Instant::now();
+    board.expose((0, side / 2, side / 2))?;
+    let expose = started.elapsed();
+
+    let mut exposed = 0;
+    let mut mine = None;
+    for coord in board.coordinates() {
+        let tile = board.tile(coord)?;
+        exposed += usize::from(tile.exposed);
+        if tile.mined() {
+            mine = Some(coord);
+        }
+    }
+
+    let started = Instant::now();
+    for _ in 0..WON_CALLS {
+        black_box(black_box(&board).won());
+    }
+    let won = started.elapsed() / WON_CALLS;
+
+    let started = Instant::now();
+    if let Some(mine) = mine.filter(|_| !board.state().is_over()) {
+        board.expose(mine)?;
+    }
+    let lose = started.elapsed();
+
+    Ok(Timings {
+        tiles,
+        mines: board.mines(),
+        new,
+        expose,
+        exposed,
+        won,
+        lose,
+    })
+}
+
+impl Timings {
+    /// The column headings lining up with each row of timings.
+    pub(crate) const HEADER: &'static str =
+        "      tiles       mines         new      expose     exposed         won        lose";
+}
+
+impl fmt::Display for Timings {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{:>11} {:>11} {:>11.3?} {:>11.3?} {:>11} {:>11.3?} {:>11.3?}",
+            self.tiles, self.mines, self.new, self.expose, self.exposed, self.won, self.lose,
+        )
+    }
+}
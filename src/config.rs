@@ -0,0 +1,358 @@
+use crate::{
+    error::Error,
+    keymap::{Action, KeyName, Preset},
+    mask::Shape,
+    menu::Difficulty,
+    topology::{Grid, Neighbourhood, Wrap},
+    ui::{Glyphs, Render, Theme},
+};
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::BTreeMap,
+    path::{Path, PathBuf},
+};
+
+/// Values for the command line options, named after their long flags. Any left out fall back to
+/// the next place options come from.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields, rename_all = "kebab-case")]
+pub(crate) struct Options {
+    pub(crate) rows: Option<usize>,
+    pub(crate) columns: Option<usize>,
+    pub(crate) layers: Option<usize>,
+    pub(crate) mines: Option<usize>,
+    pub(crate) mines_per_tile: Option<u8>,
+    pub(crate) open_start: Option<bool>,
+    pub(crate) no_guess: Option<bool>,
+    pub(crate) attempts: Option<usize>,
+    pub(crate) seed: Option<u64>,
+    pub(crate) grid: Option<Grid>,
+    pub(crate) wrap: Option<Wrap>,
+    pub(crate) neighbourhood: Option<Neighbourhood>,
+    pub(crate) shape: Option<Shape>,
+    pub(crate) mask: Option<PathBuf>,
+    pub(crate) difficulty: Option<Difficulty>,
+    pub(crate) cell_width: Option<usize>,
+    pub(crate) cell_height: Option<usize>,
+    pub(crate) save_board: Option<PathBuf>,
+    pub(crate) no_question_marks: Option<bool>,
+    pub(crate) scroll_margin: Option<usize>,
+    pub(crate) render: Option<Render>,
+    pub(crate) theme: Option<Theme>,
+    pub(crate) glyphs: Option<Glyphs>,
+    pub(crate) keys: Option<Preset>,
+    pub(crate) keymap: Option<PathBuf>,
+}
+
+impl Options {
+    /// These options, with the ones left out taken from `fallback`. A difficulty and a size both
+    /// size the board, so whichever these options give replaces the other in `fallback`.
+    pub(crate) fn or(self, mut fallback: Self) -> Self {
+        if self.difficulty.is_some() {
+            (fallback.rows, fallback.columns, fallback.mines) = (None, None, None);
+        } else if self.rows.is_some() || self.columns.is_some() || self.mines.is_some() {
+            fallback.difficulty = None;
+        }
+        Self {
+            rows: self.rows.or(fallback.rows),
+            columns: self.columns.or(fallback.columns),
+            layers: self.layers.or(fallback.layers),
+            mines: self.mines.or(fallback.mines),
+            mines_per_tile: self.mines_per_tile.or(fallback.mines_per_tile),
+            open_start: self.open_start.or(fallback.open_start),
+            no_guess: self.no_guess.or(fallback.no_guess),
+            attempts: self.attempts.or(fallback.attempts),
+            seed: self.seed.or(fallback.seed),
+            grid: self.grid.or(fallback.grid),
+            wrap: self.wrap.or(fallback.wrap),
+            neighbourhood: self.neighbourhood.or(fallback.neighbourhood),
+            shape: self.shape.or(fallback.shape),
+            mask: self.mask.or(fallback.mask),
+            difficulty: self.difficulty.or(fallback.difficulty),
+            cell_width: self.cell_width.or(fallback.cell_width),
+            cell_height: self.cell_height.or(fallback.cell_height),
+            save_board: self.save_board.or(fallback.save_board),
+            no_question_marks: self.no_question_marks.or(fallback.no_question_marks),
+            scroll_margin: self.scroll_margin.or(fallback.scroll_margin),
+            render: self.render.or(fallback.render),
+            theme: self.theme.or(fallback.theme),
+            glyphs: self.glyphs.or(fallback.glyphs),
+            keys: self.keys.or(fallback.keys),
+            keymap: self.keymap.or(fallback.keymap),
+        }
+    }
+
+    /// Check that the sizes given are at least one, since no board has zero rows, columns or
+    /// layers, and tiles must hold at least one mine each, and that a difficulty isn't given
+    /// along with the size it sets.
+    pub(crate) fn validate(&self) -> Result<(), Error> {
+        if self.difficulty.is_some()
+            && (self.rows.is_some() || self.columns.is_some() || self.mines.is_some())
+        {
+            return Err(Error::DifficultyWithSize);
+        }
+        let sizes = [
+            ("rows", self.rows),
+            ("columns", self.columns),
+            ("layers", self.layers),
+            ("mines-per-tile", self.mines_per_tile.map(usize::from)),
+        ];
+        match sizes.into_iter().find(|&(_, value)| value == Some(0)) {
+            Some((name, _)) => Err(Error::ZeroOption(name)),
+            None => Ok(()),
+        }
+    }
+
+    /// These options, with the paths in them taken to be relative to `dir`.
+    pub(crate) fn relative_to(self, dir: &Path) -> Self {
+        Self {
+            mask: self.mask.map(|path| dir.join(path)),
+            save_board: self.save_board.map(|path| dir.join(path)),
+            keymap: self.keymap.map(|path| dir.join(path)),
+            ..self
+        }
+    }
+
+    /// Whether these options choose the size of the board.
+    pub(crate) fn sizes_board(&self) -> bool {
+        self.rows.is_some()
+            || self.columns.is_some()
+            || self.mines.is_some()
+            || self.mask.is_some()
+            || self.difficulty.is_some()
+    }
+}
+
+/// A configuration file: the options to use when they aren't given on the command line, named
+/// presets of options to use instead, and keys to bind actions to. Paths are relative to the
+/// file, and a preset's difficulty or size replaces the one in the defaults. For example
+///
+/// ```toml
+/// preset = "big"
+///
+/// [defaults]
+/// render = "grid"
+/// glyphs = "symbols"
+///
+/// [presets.big]
+/// rows = 30
+/// columns = 30
+/// mines = 150
+///
+/// [bindings]
+/// expose = ["space", "enter"]
+/// ```
+#[derive(Debug, Default, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub(crate) struct Config {
+    /// The preset to use when none is asked for.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) preset: Option<String>,
+    pub(crate) defaults: Options,
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    pub(crate) presets: BTreeMap<String, Options>,
+    pub(crate) bindings: BTreeMap<Action, Vec<KeyName>>,
+}
+
+impl Config {
+    pub(crate) fn parse(text: &str) -> Result<Self, Error> {
+        let config = toml::from_str::<Self>(text).map_err(Error::ParseConfig)?;
+        config.defaults.validate()?;
+        for (name, options) in &config.presets {
+            options
+                .validate()
+                .map_err(|e| Error::InvalidPreset(name.clone(), Box::new(e)))?;
+        }
+        Ok(config)
+    }
+
+    /// The configuration with the paths in it taken to be relative to `dir`, the directory the
+    /// file is in.
+    pub(crate) fn relative_to(self, dir: &Path) -> Self {
+        Self {
+            defaults: self.defaults.relative_to(dir),
+            presets: self
+                .presets
+                .into_iter()
+                .map(|(name, options)| (name, options.relative_to(dir)))
+                .collect(),
+            ..self
+        }
+    }
+
+    /// The configuration as the text of a configuration file.
+    pub(crate) fn to_toml(&self) -> Result<String, Error> {
+        toml::to_string(self).map_err(Error::WriteConfig)
+    }
+
+    /// Where the configuration file is read from when no other is given:
+    /// `$XDG_CONFIG_HOME/minesweep/config.toml`, or `~/.config/minesweep/config.toml`.
+    pub(crate) fn default_path() -> Option<PathBuf> {
+        let non_empty = |name| std::env::var_os(name).filter(|value| !value.is_empty());
+        let config_home = non_empty("XDG_CONFIG_HOME")
+            .map(PathBuf::from)
+            .or_else(|| non_empty("HOME").map(|home| Path::new(&home).join(".config")))?;
+        Some(config_home.join("minesweep").join("config.toml"))
+    }
+
+    /// The options of `preset`, or of the file's own preset if none is given, falling back to
+    /// the file's defaults.
+    pub(crate) fn options(&self, preset: Option<&str>) -> Result<Options, Error> {
+        let Some(name) = preset.or(self.preset.as_deref()) else {
+            return Ok(self.defaults.clone());
+        };
+        let options = self
+            .presets
+            .get(name)
+            .ok_or_else(|| Error::UnknownPreset(name.to_owned()))?;
+        Ok(options.clone().or(self.defaults.clone()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn presets_fall_back_to_the_defaults() {
+        let config = Config::parse(
+            r#"
+            preset = "big"
+
+            [defaults]
+            rows = 12
+            theme = "light"
+
+            [presets.big]
+            rows = 30
+            columns = 30
+            "#,
+        )
+        .unwrap();
+
+        let big = config.options(None).unwrap();
+        assert_eq!((big.rows, big.columns), (Some(30), Some(30)));
+        assert_eq!(big.theme, Some(Theme::Light));
+        assert!(big.sizes_board());
+
+        assert!(matches!(
+            config.options(Some("huge")),
+            Err(Error::UnknownPreset(name)) if name == "huge"
+        ));
+    }
+
+    #[test]
+    fn options_given_first_win() {
+        let given = Options {
+            rows: Some(5),
+            ..Options::default()
+        };
+        let fallback = Options {
+            rows: Some(9),
+            mines: Some(3),
+            ..Options::default()
+        };
+        let options = given.or(fallback);
+        assert_eq!((options.rows, options.mines), (Some(5), Some(3)));
+    }
+
+    #[test]
+    fn a_difficulty_and_a_size_replace_each_other() {
+        let config = Config::parse(
+            r#"
+            [defaults]
+            difficulty = "expert"
+
+            [presets.tall]
+            rows = 40
+            "#,
+        )
+        .unwrap();
+        let tall = config.options(Some("tall")).unwrap();
+        assert_eq!((tall.rows, tall.difficulty), (Some(40), None));
+        let easy = Options {
+            difficulty: Some(Difficulty::Beginner),
+            ..Options::default()
+        }
+        .or(Options {
+            rows: Some(12),
+            mines: Some(20),
+            ..Options::default()
+        });
+        assert_eq!((easy.rows, easy.mines), (None, None));
+        assert_eq!(easy.difficulty, Some(Difficulty::Beginner));
+
+        assert!(matches!(
+            Config::parse("[defaults]\ndifficulty = \"expert\"\nmines = 10\n"),
+            Err(Error::DifficultyWithSize)
+        ));
+    }
+
+    #[test]
+    fn paths_are_relative_to_the_file() {
+        let config = Config::parse(
+            r#"
+            [defaults]
+            mask = "shapes/star.txt"
+            keymap = "/etc/minesweep/keys.toml"
+
+            [presets.saved]
+            save-board = "board.txt"
+            "#,
+        )
+        .unwrap()
+        .relative_to(Path::new("/home/me/.config/minesweep"));
+        assert_eq!(
+            config.defaults.mask.as_deref(),
+            Some(Path::new("/home/me/.config/minesweep/shapes/star.txt"))
+        );
+        assert_eq!(
+            config.defaults.keymap.as_deref(),
+            Some(Path::new("/etc/minesweep/keys.toml"))
+        );
+        assert_eq!(
+            config.presets["saved"].save_board.as_deref(),
+            Some(Path::new("/home/me/.config/minesweep/board.txt"))
+        );
+    }
+
+    #[test]
+    fn rejects_unknown_options() {
+        assert!(matches!(
+            Config::parse("[defaults]\nrow = 3\n"),
+            Err(Error::ParseConfig(_))
+        ));
+    }
+
+    #[test]
+    fn rejects_zero_sizes() {
+        assert!(matches!(
+            Config::parse("[defaults]\nlayers = 0\n"),
+            Err(Error::ZeroOption("layers"))
+        ));
+        let Err(Error::InvalidPreset(name, error)) =
+            Config::parse("[presets.heavy]\nmines-per-tile = 0\n")
+        else {
+            panic!("accepted a preset with no room for mines");
+        };
+        assert_eq!(name, "heavy");
+        assert!(matches!(*error, Error::ZeroOption("mines-per-tile")));
+    }
+
+    #[test]
+    fn written_configurations_read_back() {
+        let mut config = Config::default();
+        config.defaults.mines_per_tile = Some(2);
+        config.presets.insert(
+            "wide".to_owned(),
+            Options {
+                columns: Some(40),
+                ..Options::default()
+            },
+        );
+
+        let read = Config::parse(&config.to_toml().unwrap()).unwrap();
+        assert_eq!(read.defaults.mines_per_tile, Some(2));
+        assert_eq!(read.presets["wide"].columns, Some(40));
+    }
+}
@@ -1,7 +1,15 @@
+use crate::{
+    sweep::{Coordinate, GameState},
+    topology::Neighbourhood,
+};
+
 #[derive(Debug, thiserror::Error)]
 pub(crate) enum Error {
     #[error("failed to get tile at coordinate: {0:?}")]
-    GetTile((usize, usize)),
+    GetTile(Coordinate),
+
+    #[error("the game is already over: {0:?}")]
+    GameOver(GameState),
 
     #[error("failed to draw to terminal")]
     DrawToTerminal(#[source] std::io::Error),
@@ -21,6 +29,127 @@ pub(crate) enum Error {
     #[error("failed to create terminal object")]
     CreateTerminal(#[source] std::io::Error),
 
+    #[error("failed to switch reporting mouse motion")]
+    ReportMouseMotion(#[source] std::io::Error),
+
+    #[error(
+        "failed to generate a board with {mines} mines in {tiles} tiles that can be solved without guessing after {attempts} attempts; try fewer mines or more attempts"
+    )]
+    GenerateNoGuessBoard {
+        attempts: usize,
+        mines: usize,
+        tiles: usize,
+    },
+
+    #[error(
+        "a board can have at most {max} rows and {max} columns, not {rows}x{columns}",
+        max = crate::sweep::Settings::MAX_SIDE
+    )]
+    BoardTooBig { rows: usize, columns: usize },
+
+    #[error("{mines} mines don't fit in {tiles} tiles, which leave room for at most {max_mines}")]
+    TooManyMines {
+        mines: usize,
+        tiles: usize,
+        max_mines: usize,
+    },
+
+    #[error("unexpected character {character:?} at line {line}, column {column} of board layout; expected a tile (one of `.`, `*`, `2`-`9`, `o`, `f`, `F` or `#`), or `!` or `?` after a hidden tile")]
+    ParseLayout {
+        line: usize,
+        column: usize,
+        character: char,
+    },
+
+    #[error("line {line} of board layout has {found} columns, expected {expected}")]
+    RaggedLayout {
+        line: usize,
+        expected: usize,
+        found: usize,
+    },
+
+    #[error(
+        "the layer of board layout ending at line {line} has {found} rows, expected {expected}"
+    )]
+    UnevenLayers {
+        line: usize,
+        expected: usize,
+        found: usize,
+    },
+
+    #[error("board layout has {flags} flags but only {mines} mines")]
+    TooManyFlags { flags: usize, mines: usize },
+
+    #[error("board layout has no tiles")]
+    EmptyLayout,
+
+    #[error("the tile at {coord:?} can't be written to a board layout, as {reason}")]
+    UnrepresentableTile {
+        coord: Coordinate,
+        reason: &'static str,
+    },
+
+    #[error("unexpected character {character:?} at line {line}, column {column} of mask; expected `.` or `#`")]
+    ParseMask {
+        line: usize,
+        column: usize,
+        character: char,
+    },
+
+    #[error("line {line} of mask has {found} columns, expected {expected}")]
+    RaggedMask {
+        line: usize,
+        expected: usize,
+        found: usize,
+    },
+
+    #[error("the mask leaves no tiles on the board")]
+    EmptyMask,
+
+    #[error("a {mask_rows}x{mask_columns} mask doesn't fit a board of {rows}x{columns} tiles")]
+    MaskSize {
+        mask_rows: usize,
+        mask_columns: usize,
+        rows: usize,
+        columns: usize,
+    },
+
+    #[error("invalid keymap")]
+    ParseKeymap(#[source] toml::de::Error),
+
+    #[error("invalid configuration")]
+    ParseConfig(#[source] toml::de::Error),
+
+    #[error("failed to write configuration")]
+    WriteConfig(#[source] toml::ser::Error),
+
+    #[error("`{0}` must be at least 1")]
+    ZeroOption(&'static str),
+
+    #[error("`difficulty` can't be given along with the `rows`, `columns` or `mines` it sets")]
+    DifficultyWithSize,
+
+    #[error("invalid preset {0:?}")]
+    InvalidPreset(String, #[source] Box<Error>),
+
+    #[error("no preset named {0:?} in the configuration")]
+    UnknownPreset(String),
+
+    #[error("failed to write board layout to {0:?}")]
+    WriteLayout(std::path::PathBuf, #[source] std::io::Error),
+
+    #[error("a hex grid can only wrap into a torus with an even number of rows, not {0}")]
+    OddHexTorus(usize),
+
+    #[error("a hex grid can't wrap into a mobius strip")]
+    HexMobius,
+
+    #[error("a hex grid can't use the {0} neighbourhood")]
+    HexNeighbourhood(Neighbourhood),
+
+    #[error("cells must be at least 3x3, not {width}x{height}")]
+    CellTooSmall { width: usize, height: usize },
+
     #[error("failed to convert usize to u16")]
     ConvertUsizeToU16(#[source] std::num::TryFromIntError),
 }
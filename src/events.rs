@@ -1,5 +1,8 @@
 use std::{io, sync::mpsc, thread, time::Duration};
-use termion::{event::Key, input::TermRead};
+use termion::{
+    event::{self, MouseEvent},
+    input::TermRead,
+};
 
 pub(crate) enum Event<I> {
     Input(I),
@@ -9,7 +12,7 @@ pub(crate) enum Event<I> {
 /// A small event handler that wrap termion input and tick events. Each event
 /// type is handled in its own thread and returned to a common `Receiver`
 pub(crate) struct Events {
-    rx: mpsc::Receiver<Event<Key>>,
+    rx: mpsc::Receiver<Event<event::Event>>,
     _input_handle: thread::JoinHandle<()>,
     _tick_handle: thread::JoinHandle<()>,
 }
@@ -40,8 +43,8 @@ impl Events {
                 let tx = tx.clone();
                 thread::spawn(move || {
                     let stdin = io::stdin();
-                    for key in stdin.keys().flatten() {
-                        if let Err(err) = tx.send(Event::Input(key)) {
+                    for input in stdin.events().flatten() {
+                        if let Err(err) = tx.send(Event::Input(motion(&input).unwrap_or(input))) {
                             eprintln!("{err}");
                             return;
                         }
@@ -59,7 +62,89 @@ impl Events {
         }
     }
 
-    pub(crate) fn next(&self) -> Result<Event<Key>, mpsc::RecvError> {
+    pub(crate) fn next(&self) -> Result<Event<event::Event>, mpsc::RecvError> {
         self.rx.recv()
     }
 }
+
+/// Termion only understands the mouse moving with the left button held, so read the motion
+/// reported while hovering or holding another button as a hold too.
+fn motion(input: &event::Event) -> Option<event::Event> {
+    let event::Event::Unsupported(bytes) = input else {
+        return None;
+    };
+    // SGR mouse reports look like `ESC [ < button ; x ; y M`, motion adding 32 to the button
+    let report = std::str::from_utf8(bytes.strip_prefix(b"\x1b[<")?.strip_suffix(b"M")?).ok()?;
+    let mut numbers = report.split(';').map(str::parse::<u16>);
+    let (Some(Ok(33..=35)), Some(Ok(x)), Some(Ok(y)), None) = (
+        numbers.next(),
+        numbers.next(),
+        numbers.next(),
+        numbers.next(),
+    ) else {
+        return None;
+    };
+    Some(event::Event::Mouse(MouseEvent::Hold(x, y)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use termion::event::{Key, MouseButton};
+
+    /// The event termion reads from `bytes`, as the input thread would, then any motion in it.
+    fn read(bytes: &[u8]) -> event::Event {
+        let mut rest = bytes[1..].iter().map(|&byte| Ok(byte));
+        // like `TermRead::events`, keep the bytes of anything termion can't parse
+        let input = event::parse_event(bytes[0], &mut rest)
+            .unwrap_or_else(|_| event::Event::Unsupported(bytes.to_vec()));
+        motion(&input).unwrap_or(input)
+    }
+
+    #[test]
+    fn hovering_and_holding_any_button_is_a_hold() {
+        // no button, the middle button and the right button held while moving
+        for button in [35, 33, 34] {
+            assert_eq!(
+                read(format!("\x1b[<{button};12;7M").as_bytes()),
+                event::Event::Mouse(MouseEvent::Hold(12, 7))
+            );
+        }
+        // termion reads moving with the left button held by itself
+        assert_eq!(
+            read(b"\x1b[<32;3;4M"),
+            event::Event::Mouse(MouseEvent::Hold(3, 4))
+        );
+    }
+
+    #[test]
+    fn presses_and_releases_pass_through() {
+        assert_eq!(
+            read(b"\x1b[<2;5;6M"),
+            event::Event::Mouse(MouseEvent::Press(MouseButton::Right, 5, 6))
+        );
+        assert_eq!(
+            read(b"\x1b[<0;5;6m"),
+            event::Event::Mouse(MouseEvent::Release(5, 6))
+        );
+        assert_eq!(read(b"q"), event::Event::Key(Key::Char('q')));
+    }
+
+    #[test]
+    fn malformed_reports_are_not_motion() {
+        let unsupported = |bytes: &[u8]| event::Event::Unsupported(bytes.to_vec());
+        for bytes in [
+            // a release while moving, which no terminal sends
+            &b"\x1b[<35;1;1m"[..],
+            // a button that isn't a motion
+            b"\x1b[<36;1;1M",
+            // coordinates missing, extra or past what a terminal reports
+            b"\x1b[<35;1M",
+            b"\x1b[<35;1;1;1M",
+            b"\x1b[<35;70000;1M",
+            b"\x1b[<35;x;1M",
+        ] {
+            assert_eq!(motion(&unsupported(bytes)), None, "{bytes:?}");
+        }
+    }
+}
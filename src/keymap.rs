@@ -0,0 +1,438 @@
+use crate::error::Error;
+use serde::{Deserialize, Serialize};
+use std::{collections::BTreeMap, fmt, str::FromStr};
+use termion::event::Key;
+
+/// Something to do with a key press.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub(crate) enum Action {
+    Up,
+    Down,
+    Left,
+    Right,
+    UpLeft,
+    UpRight,
+    DownLeft,
+    DownRight,
+    /// Move a screenful at a time.
+    PanUp,
+    PanDown,
+    PanLeft,
+    PanRight,
+    PreviousLayer,
+    NextLayer,
+    Expose,
+    /// Flag the tile, or cycle through a question mark when they're on.
+    Flag,
+    /// Take a flag off a tile holding several.
+    Unflag,
+    ExposeNeighbours,
+    FlagNeighbours,
+    Undo,
+    Redo,
+    MineChances,
+    Hint,
+    SwitchRenderer,
+    ZoomIn,
+    ZoomOut,
+    /// Fit the cells to the terminal again.
+    Fit,
+    SaveBoard,
+    /// Choose a board to play next from the menu.
+    NewGame,
+    Help,
+    Quit,
+}
+
+/// A starting set of key bindings.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub(crate) enum Preset {
+    /// hjkl or the arrow keys to move, and home, page up, end and page down for diagonals.
+    #[default]
+    Default,
+    /// hjkl to move and yubn for diagonals, like roguelikes.
+    Vim,
+    /// wasd to move and qezc for diagonals.
+    Wasd,
+    /// The digits of a numeric keypad, with 5 exposing the tile in the middle of them.
+    Numpad,
+}
+
+impl fmt::Display for Preset {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Self::Default => "default",
+            Self::Vim => "vim",
+            Self::Wasd => "wasd",
+            Self::Numpad => "numpad",
+        })
+    }
+}
+
+impl FromStr for Preset {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "default" => Ok(Self::Default),
+            "vim" => Ok(Self::Vim),
+            "wasd" => Ok(Self::Wasd),
+            "numpad" => Ok(Self::Numpad),
+            _ => Err(format!(
+                "unknown keymap preset {s:?}, expected `default`, `vim`, `wasd` or `numpad`"
+            )),
+        }
+    }
+}
+
+/// A key as written in a keymap file and shown in the help.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(try_from = "String")]
+pub(crate) struct KeyName(pub(crate) Key);
+
+impl fmt::Display for KeyName {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.0 {
+            Key::Char(' ') => f.write_str("space"),
+            Key::Char('\n') => f.write_str("enter"),
+            Key::Char('\t') => f.write_str("tab"),
+            Key::Char(c) => write!(f, "{c}"),
+            Key::Ctrl(c) => write!(f, "ctrl-{c}"),
+            Key::Alt(c) => write!(f, "alt-{c}"),
+            Key::F(n) => write!(f, "f{n}"),
+            Key::Up => f.write_str("↑"),
+            Key::Down => f.write_str("↓"),
+            Key::Left => f.write_str("←"),
+            Key::Right => f.write_str("→"),
+            Key::Home => f.write_str("home"),
+            Key::End => f.write_str("end"),
+            Key::PageUp => f.write_str("pgup"),
+            Key::PageDown => f.write_str("pgdn"),
+            Key::Insert => f.write_str("insert"),
+            Key::Delete => f.write_str("delete"),
+            Key::Backspace => f.write_str("backspace"),
+            Key::BackTab => f.write_str("backtab"),
+            Key::Esc => f.write_str("esc"),
+            _ => f.write_str("?"),
+        }
+    }
+}
+
+impl FromStr for KeyName {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut chars = s.chars();
+        let key = match (chars.next(), chars.next()) {
+            (Some(c), None) => Key::Char(c),
+            _ => match s {
+                "space" => Key::Char(' '),
+                "enter" => Key::Char('\n'),
+                "tab" => Key::Char('\t'),
+                "up" => Key::Up,
+                "down" => Key::Down,
+                "left" => Key::Left,
+                "right" => Key::Right,
+                "home" => Key::Home,
+                "end" => Key::End,
+                "pgup" => Key::PageUp,
+                "pgdn" => Key::PageDown,
+                "insert" => Key::Insert,
+                "delete" => Key::Delete,
+                "backspace" => Key::Backspace,
+                "backtab" => Key::BackTab,
+                "esc" => Key::Esc,
+                _ => {
+                    let single = |rest: &str| {
+                        let mut chars = rest.chars();
+                        chars.next().filter(|_| chars.next().is_none())
+                    };
+                    if let Some(c) = s.strip_prefix("ctrl-").and_then(single) {
+                        Key::Ctrl(c)
+                    } else if let Some(c) = s.strip_prefix("alt-").and_then(single) {
+                        Key::Alt(c)
+                    } else if let Some(n) = s
+                        .strip_prefix('f')
+                        .and_then(|n| n.parse().ok())
+                        .filter(|n| (1..=12).contains(n))
+                    {
+                        Key::F(n)
+                    } else {
+                        return Err(format!("unknown key {s:?}"));
+                    }
+                }
+            },
+        };
+        Ok(Self(key))
+    }
+}
+
+impl Serialize for KeyName {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        // arrows are drawn as such in the help, but named in keymap files
+        match self.0 {
+            Key::Up => serializer.serialize_str("up"),
+            Key::Down => serializer.serialize_str("down"),
+            Key::Left => serializer.serialize_str("left"),
+            Key::Right => serializer.serialize_str("right"),
+            _ => serializer.collect_str(self),
+        }
+    }
+}
+
+impl TryFrom<String> for KeyName {
+    type Error = String;
+
+    fn try_from(s: String) -> Result<Self, Self::Error> {
+        s.parse()
+    }
+}
+
+/// A keymap file: a preset to start from, and the keys to bind actions to instead of the
+/// preset's, for example
+///
+/// ```toml
+/// preset = "vim"
+///
+/// [bindings]
+/// expose = ["space", "enter"]
+/// hint = []
+/// ```
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub(crate) struct KeymapFile {
+    pub(crate) preset: Option<Preset>,
+    #[serde(default)]
+    pub(crate) bindings: BTreeMap<Action, Vec<KeyName>>,
+}
+
+impl KeymapFile {
+    pub(crate) fn parse(text: &str) -> Result<Self, Error> {
+        toml::from_str(text).map_err(Error::ParseKeymap)
+    }
+}
+
+/// Which action each key does.
+#[derive(Debug, Clone)]
+pub(crate) struct Keymap {
+    // in the order the keys of each action are shown in the help
+    bindings: Vec<(Key, Action)>,
+}
+
+impl Default for Keymap {
+    fn default() -> Self {
+        Self::preset(Preset::Default)
+    }
+}
+
+impl Keymap {
+    pub(crate) fn preset(preset: Preset) -> Self {
+        use Action::*;
+
+        let mut keymap = Self {
+            bindings: [
+                (Left, &[Key::Char('h'), Key::Left][..]),
+                (Down, &[Key::Char('j'), Key::Down]),
+                (Up, &[Key::Char('k'), Key::Up]),
+                (Right, &[Key::Char('l'), Key::Right]),
+                (UpLeft, &[Key::Home]),
+                (UpRight, &[Key::PageUp]),
+                (DownLeft, &[Key::End]),
+                (DownRight, &[Key::PageDown]),
+                (PanLeft, &[Key::Char('H')]),
+                (PanDown, &[Key::Char('J')]),
+                (PanUp, &[Key::Char('K')]),
+                (PanRight, &[Key::Char('L')]),
+                (PreviousLayer, &[Key::Char('[')]),
+                (NextLayer, &[Key::Char(']')]),
+                (Expose, &[Key::Char(' ')]),
+                (Flag, &[Key::Char('f')]),
+                (Unflag, &[Key::Char('x')]),
+                (ExposeNeighbours, &[Key::Char('c')]),
+                (FlagNeighbours, &[Key::Char('F')]),
+                (Undo, &[Key::Char('u')]),
+                (Redo, &[Key::Ctrl('r')]),
+                (MineChances, &[Key::Char('p')]),
+                (Hint, &[Key::Char('i')]),
+                (SwitchRenderer, &[Key::Char('r')]),
+                (ZoomIn, &[Key::Char('+'), Key::Char('=')]),
+                (ZoomOut, &[Key::Char('-')]),
+                (Fit, &[Key::Char('0')]),
+                (SaveBoard, &[Key::Char('s')]),
+                (NewGame, &[Key::Char('n')]),
+                (Help, &[Key::Char('?')]),
+                (Quit, &[Key::Char('q')]),
+            ]
+            .into_iter()
+            .flat_map(|(action, keys)| keys.iter().map(move |&key| (key, action)))
+            .collect(),
+        };
+
+        let overrides: &[(Action, &[Key])] = match preset {
+            Preset::Default => &[],
+            Preset::Vim => &[
+                (UpLeft, &[Key::Char('y')]),
+                (UpRight, &[Key::Char('u')]),
+                (DownLeft, &[Key::Char('b')]),
+                (DownRight, &[Key::Char('n')]),
+                (Undo, &[Key::Char('U')]),
+                (NewGame, &[Key::Char('N')]),
+            ],
+            Preset::Wasd => &[
+                (Left, &[Key::Char('a'), Key::Left]),
+                (Down, &[Key::Char('s'), Key::Down]),
+                (Up, &[Key::Char('w'), Key::Up]),
+                (Right, &[Key::Char('d'), Key::Right]),
+                (UpLeft, &[Key::Char('q')]),
+                (UpRight, &[Key::Char('e')]),
+                (DownLeft, &[Key::Char('z')]),
+                (DownRight, &[Key::Char('c')]),
+                (PanLeft, &[Key::Char('A')]),
+                (PanDown, &[Key::Char('S')]),
+                (PanUp, &[Key::Char('W')]),
+                (PanRight, &[Key::Char('D')]),
+                (ExposeNeighbours, &[Key::Char('g')]),
+                (FlagNeighbours, &[Key::Char('G')]),
+                (SaveBoard, &[Key::Ctrl('s')]),
+                (Quit, &[Key::Esc]),
+            ],
+            Preset::Numpad => &[
+                (Left, &[Key::Char('4'), Key::Left]),
+                (Down, &[Key::Char('2'), Key::Down]),
+                (Up, &[Key::Char('8'), Key::Up]),
+                (Right, &[Key::Char('6'), Key::Right]),
+                (UpLeft, &[Key::Char('7')]),
+                (UpRight, &[Key::Char('9')]),
+                (DownLeft, &[Key::Char('1')]),
+                (DownRight, &[Key::Char('3')]),
+                (Expose, &[Key::Char('5'), Key::Char(' ')]),
+                (Flag, &[Key::Char('0')]),
+                (ExposeNeighbours, &[Key::Char('.')]),
+                (Fit, &[Key::Char('*')]),
+                (Help, &[Key::Char('?'), Key::Char('/')]),
+            ],
+        };
+        for &(action, keys) in overrides {
+            keymap.rebind(action, keys);
+        }
+        keymap
+    }
+
+    /// The keymap a keymap file describes, starting from `preset` if given instead of the
+    /// file's.
+    pub(crate) fn from_file(file: &KeymapFile, preset: Option<Preset>) -> Self {
+        let mut keymap = Self::preset(preset.or(file.preset).unwrap_or_default());
+        for (&action, keys) in &file.bindings {
+            keymap.rebind(action, &keys.iter().map(|key| key.0).collect::<Vec<_>>());
+        }
+        keymap
+    }
+
+    /// Bind `action` to `keys` alone, taking them from any other action bound to them.
+    pub(crate) fn rebind(&mut self, action: Action, keys: &[Key]) {
+        self.bindings
+            .retain(|&(key, bound)| bound != action && !keys.contains(&key));
+        self.bindings.extend(keys.iter().map(|&key| (key, action)));
+    }
+
+    pub(crate) fn action(&self, key: Key) -> Option<Action> {
+        self.bindings
+            .iter()
+            .find(|&&(bound, _)| bound == key)
+            .map(|&(_, action)| action)
+    }
+
+    /// The keys bound to every action, including the actions bound to none.
+    pub(crate) fn bindings(&self) -> BTreeMap<Action, Vec<KeyName>> {
+        // every action has keys in the default preset
+        let mut bindings = Self::default()
+            .bindings
+            .into_iter()
+            .map(|(_, action)| (action, Vec::new()))
+            .collect::<BTreeMap<_, _>>();
+        for &(key, action) in &self.bindings {
+            bindings.entry(action).or_default().push(KeyName(key));
+        }
+        bindings
+    }
+
+    /// The keys bound to `action`, in the order they were bound.
+    pub(crate) fn keys(&self, action: Action) -> impl Iterator<Item = KeyName> + '_ {
+        self.bindings
+            .iter()
+            .filter(move |&&(_, bound)| bound == action)
+            .map(|&(key, _)| KeyName(key))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn key_names_read_back() {
+        for name in [
+            "x", "space", "enter", "tab", "ctrl-z", "alt-x", "f5", "up", "home", "pgdn", "esc",
+        ] {
+            let key = name.parse::<KeyName>().unwrap();
+            let written = toml::Value::try_from(key).unwrap();
+            assert_eq!(written.as_str(), Some(name));
+        }
+        assert!("f13".parse::<KeyName>().is_err());
+        assert!("ctrl-xy".parse::<KeyName>().is_err());
+        assert!("hyper".parse::<KeyName>().is_err());
+    }
+
+    #[test]
+    fn presets_bind_each_key_once() {
+        for preset in [Preset::Default, Preset::Vim, Preset::Wasd, Preset::Numpad] {
+            let keymap = Keymap::preset(preset);
+            for (i, &(key, _)) in keymap.bindings.iter().enumerate() {
+                assert!(
+                    keymap.bindings[..i].iter().all(|&(bound, _)| bound != key),
+                    "{preset} binds {} twice",
+                    KeyName(key)
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn files_rebind_keys_on_top_of_a_preset() {
+        let file = KeymapFile::parse(
+            r#"
+            preset = "vim"
+
+            [bindings]
+            expose = ["enter", "h"]
+            hint = []
+            "#,
+        )
+        .unwrap();
+        let keymap = Keymap::from_file(&file, None);
+
+        assert_eq!(keymap.action(Key::Char('\n')), Some(Action::Expose));
+        // the file takes `h` from moving left
+        assert_eq!(keymap.action(Key::Char('h')), Some(Action::Expose));
+        assert_eq!(keymap.keys(Action::Hint).count(), 0);
+        assert_eq!(keymap.bindings()[&Action::Hint], Vec::new());
+
+        // a preset given on the command line replaces the file's
+        let keymap = Keymap::from_file(&file, Some(Preset::Wasd));
+        assert_eq!(keymap.action(Key::Char('w')), Some(Action::Up));
+    }
+
+    #[test]
+    fn files_reject_unknown_keys_and_actions() {
+        assert!(matches!(
+            KeymapFile::parse("[bindings]\nexpose = [\"hyper\"]\n"),
+            Err(Error::ParseKeymap(_))
+        ));
+        assert!(matches!(
+            KeymapFile::parse("[bindings]\nexplode = [\"x\"]\n"),
+            Err(Error::ParseKeymap(_))
+        ));
+    }
+}
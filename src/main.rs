@@ -1,13 +1,32 @@
 use anyhow::{Context, Result};
-use structopt::StructOpt;
+use config::{Config, Options};
+use keymap::{Keymap, KeymapFile, Preset};
+use mask::{Mask, Shape};
+use menu::Difficulty;
+use std::path::{Path, PathBuf};
+use structopt::{clap::ArgMatches, StructOpt};
+use sweep::{Board, Generator, SafeZone, Settings};
+use topology::{Grid, Neighbourhood, Topology, Wrap};
+use ui::{Glyphs, Render, Theme};
 
+mod bench;
+mod config;
 mod error;
 mod events;
+mod keymap;
+mod mask;
+mod menu;
+mod probability;
+mod simulate;
+mod solver;
 mod sweep;
+mod topology;
 mod ui;
+mod viewport;
 
+// the options describing how to generate a board, shared by playing and simulating
 #[derive(Debug, structopt::StructOpt)]
-struct Opt {
+struct GameOpt {
     /// The number of rows in the grid.
     #[structopt(short, long, default_value = "9")]
     rows: usize,
@@ -16,35 +35,553 @@ struct Opt {
     #[structopt(short, long, default_value = "9")]
     columns: usize,
 
+    /// The number of layers stacked on top of each other. Each tile of a
+    /// layered board also neighbours the tiles around it in the layers
+    /// directly above and below.
+    #[structopt(short = "-d", long, default_value = "1")]
+    layers: usize,
+
     /// The total number of mines in the grid. The maximum number of mines
-    /// is the product of the number of rows and the number of columns.
+    /// is the product of the number of layers, rows, columns and mines per
+    /// tile.
     #[structopt(short = "-n", long, default_value = "10")]
     mines: usize,
 
-    /// The width of each cell.
-    #[structopt(short = "-w", long, default_value = "5")]
-    cell_width: usize,
+    /// The most mines a single tile can hold. Numbers count every mine
+    /// around them, and flags stack to mark how many mines a tile holds.
+    #[structopt(long, default_value = "1")]
+    mines_per_tile: u8,
+
+    /// Keep every tile adjacent to the first exposed tile free of mines, so
+    /// that the first expose always opens a cascade.
+    #[structopt(long)]
+    open_start: bool,
+
+    /// Only generate boards that can be cleared from the first exposed tile
+    /// without guessing.
+    #[structopt(long)]
+    no_guess: bool,
+
+    /// The maximum number of layouts to try when generating a board that
+    /// can be solved without guessing.
+    #[structopt(long, default_value = "1000")]
+    attempts: usize,
+
+    /// The seed used to place mines. The same seed, dimensions, number of
+    /// mines and first exposed tile always produce the same board. Chosen
+    /// randomly if not given.
+    #[structopt(long)]
+    seed: Option<u64>,
+
+    /// The shape of the tiles: `square` tiles have eight neighbours and
+    /// `hex` tiles have six.
+    #[structopt(long, default_value = "square", possible_values = &["square", "hex"])]
+    grid: Grid,
+
+    /// Which edges of the board join up: `cylinder` joins the left and
+    /// right edges, `torus` also joins the top and bottom, and `mobius`
+    /// joins the left and right edges with a flip.
+    #[structopt(
+        long,
+        default_value = "none",
+        possible_values = &["none", "cylinder", "torus", "mobius"],
+    )]
+    wrap: Wrap,
+
+    /// Which tiles a number counts the mines of: `standard` counts every
+    /// touching tile, `orthogonal` only those sharing an edge, `knight`
+    /// those a knight's move away and `radius-2` those up to two steps
+    /// away.
+    #[structopt(
+        long,
+        default_value = "standard",
+        possible_values = &["standard", "orthogonal", "knight", "radius-2"],
+    )]
+    neighbourhood: Neighbourhood,
+
+    /// The outline of the tiles on the board, stretched to fit the number of
+    /// rows and columns.
+    #[structopt(
+        long,
+        default_value = "rectangle",
+        possible_values = &["rectangle", "diamond", "ring", "heart"],
+    )]
+    shape: Shape,
+
+    /// Load the outline of the tiles on the board from a file instead, which
+    /// overrides the number of rows and columns. Each line is a row of `.`
+    /// (tile) or `#` (gap) characters.
+    #[structopt(long, parse(from_os_str), conflicts_with = "shape")]
+    mask: Option<PathBuf>,
+}
+
+/// Replace `value` with the configured value, unless it was given on the command line.
+fn configure<T>(value: &mut T, given: bool, configured: Option<T>) {
+    if let Some(configured) = configured.filter(|_| !given) {
+        *value = configured;
+    }
+}
+
+impl GameOpt {
+    /// Take the options not given on the command line from `options`.
+    fn configure(&mut self, options: Options, matches: &ArgMatches) {
+        let given = |name| matches.occurrences_of(name) > 0;
+        configure(&mut self.rows, given("rows"), options.rows);
+        configure(&mut self.columns, given("columns"), options.columns);
+        configure(&mut self.layers, given("layers"), options.layers);
+        configure(&mut self.mines, given("mines"), options.mines);
+        configure(
+            &mut self.mines_per_tile,
+            given("mines_per_tile"),
+            options.mines_per_tile,
+        );
+        configure(
+            &mut self.open_start,
+            given("open_start"),
+            options.open_start,
+        );
+        configure(&mut self.no_guess, given("no_guess"), options.no_guess);
+        configure(&mut self.attempts, given("attempts"), options.attempts);
+        configure(&mut self.seed, given("seed"), options.seed.map(Some));
+        configure(&mut self.grid, given("grid"), options.grid);
+        configure(&mut self.wrap, given("wrap"), options.wrap);
+        configure(
+            &mut self.neighbourhood,
+            given("neighbourhood"),
+            options.neighbourhood,
+        );
+        // a shape and a mask conflict, so either on the command line overrides both
+        let outline_given = given("shape") || given("mask");
+        configure(&mut self.shape, outline_given, options.shape);
+        configure(&mut self.mask, outline_given, options.mask.map(Some));
+    }
 
-    /// The height of each cell.
-    #[structopt(short = "-H", long, default_value = "3")]
-    cell_height: usize,
+    fn options(&self) -> Options {
+        Options {
+            rows: Some(self.rows),
+            columns: Some(self.columns),
+            layers: Some(self.layers),
+            mines: Some(self.mines),
+            mines_per_tile: Some(self.mines_per_tile),
+            open_start: Some(self.open_start),
+            no_guess: Some(self.no_guess),
+            attempts: Some(self.attempts),
+            seed: self.seed,
+            grid: Some(self.grid),
+            wrap: Some(self.wrap),
+            neighbourhood: Some(self.neighbourhood),
+            shape: Some(self.shape),
+            mask: self.mask.clone(),
+            ..Options::default()
+        }
+    }
+
+    fn topology(&self) -> Topology {
+        Topology {
+            grid: self.grid,
+            wrap: self.wrap,
+            neighbourhood: self.neighbourhood,
+        }
+    }
+
+    /// The settings for generating a board, with a random seed if none was given.
+    fn settings(&self) -> Result<Settings> {
+        self.options().validate()?;
+        // outlines are drawn to the size asked for, so check it before drawing one
+        Settings::check_size(self.rows, self.columns)?;
+        let mask = if let Some(path) = &self.mask {
+            let text = std::fs::read_to_string(path)
+                .with_context(|| format!("failed to read mask from {path:?}"))?;
+            Some(Mask::parse(&text).with_context(|| format!("invalid mask in {path:?}"))?)
+        } else if self.shape != Shape::Rectangle {
+            Some(Mask::shape(self.shape, self.rows, self.columns))
+        } else {
+            None
+        };
+        let (rows, columns) = mask.as_ref().map_or((self.rows, self.columns), |mask| {
+            (mask.rows(), mask.columns())
+        });
+        let tiles = self.layers * mask.as_ref().map_or(rows * columns, Mask::tiles);
+
+        Ok(Settings::builder()
+            .layers(self.layers)
+            .rows(rows)
+            .columns(columns)
+            .mines(
+                self.mines
+                    .min(Settings::max_mines(tiles, self.mines_per_tile)),
+            )
+            .mines_per_tile(self.mines_per_tile)
+            .safe_zone(if self.open_start {
+                SafeZone::Neighbourhood
+            } else {
+                SafeZone::Tile
+            })
+            .generator(if self.no_guess {
+                Generator::NoGuess {
+                    attempts: self.attempts,
+                }
+            } else {
+                Generator::Random
+            })
+            .topology(self.topology())
+            .mask(mask)
+            .seed(self.seed.unwrap_or_else(rand::random))
+            .build())
+    }
+}
+
+#[derive(Debug, structopt::StructOpt)]
+struct Opt {
+    #[structopt(flatten)]
+    game: GameOpt,
+
+    /// The width of each cell. Cells grow to fit the board to the terminal
+    /// if neither the width nor the height is given.
+    #[structopt(short = "-w", long)]
+    cell_width: Option<usize>,
+
+    /// The height of each cell. Cells grow to fit the board to the terminal
+    /// if neither the width nor the height is given.
+    #[structopt(short = "-H", long)]
+    cell_height: Option<usize>,
+
+    /// Load the board from a layout file instead of generating one, which
+    /// overrides the number of layers, rows, columns and mines. Each line is
+    /// a row of `.` (hidden), `*` (mine), `2`-`9` (that many mines), `o`
+    /// (exposed), `f` (flagged) or `F` (flagged mine) tiles, and blank lines
+    /// separate layers. A hidden tile followed by `!` for each flag on it or
+    /// by `?` is flagged or question-marked.
+    #[structopt(long, parse(from_os_str))]
+    board: Option<PathBuf>,
+
+    /// Save the board's layout to this file when pressing `s`.
+    #[structopt(long, parse(from_os_str))]
+    save_board: Option<PathBuf>,
+
+    /// Only toggle flags when marking a tile, instead of cycling from a flag
+    /// to a question mark.
+    #[structopt(long)]
+    no_question_marks: bool,
+
+    /// How many tiles to keep between the cursor and the edges of the view
+    /// when scrolling a board too big to fit on screen.
+    #[structopt(long, default_value = "2")]
+    scroll_margin: usize,
+
+    /// How to draw the tiles: `blocks` boxes each tile, `grid` shares the
+    /// borders of neighbouring tiles, `compact` draws one character per tile
+    /// and `half-block` one colour per tile, two rows to a line. Press `r` to
+    /// switch while playing.
+    #[structopt(
+        long,
+        default_value = "blocks",
+        possible_values = &["blocks", "grid", "compact", "half-block"],
+    )]
+    render: Render,
+
+    /// The colours to draw the tiles in: `dark` or `light`.
+    #[structopt(long, default_value = "dark", possible_values = &["dark", "light"])]
+    theme: Theme,
+
+    /// The symbols for mines, flags and question marks: `emoji`, `symbols`
+    /// or `ascii` for terminals that draw emoji at the wrong width.
+    #[structopt(
+        long,
+        default_value = "emoji",
+        possible_values = &["emoji", "symbols", "ascii"],
+    )]
+    glyphs: Glyphs,
+
+    /// The set of key bindings to start from: `default`, `vim`, `wasd` or
+    /// `numpad`. Overrides the preset in a keymap file.
+    #[structopt(long, possible_values = &["default", "vim", "wasd", "numpad"])]
+    keys: Option<Preset>,
+
+    /// Load key bindings from a TOML file, with an optional `preset` to start
+    /// from and a `[bindings]` table of the keys for each action, such as
+    /// `expose = ["space", "enter"]`. Press `?` while playing to list them.
+    #[structopt(long, parse(from_os_str))]
+    keymap: Option<PathBuf>,
+
+    /// Read the configuration from this TOML file instead of
+    /// `$XDG_CONFIG_HOME/minesweep/config.toml`. Its `[defaults]` table and
+    /// `[presets.<name>]` tables give values for any of these options,
+    /// named after their long flags, and its `[bindings]` table binds keys
+    /// like a keymap file. Options given here override it.
+    #[structopt(long, parse(from_os_str))]
+    config: Option<PathBuf>,
+
+    /// Use the options of this preset from the configuration file instead
+    /// of the file's own `preset`.
+    #[structopt(long)]
+    preset: Option<String>,
+
+    /// Play a board of a built-in size: `beginner` (9x9 with 10 mines),
+    /// `intermediate` (16x16 with 40 mines) or `expert` (16x30 with 99
+    /// mines). The rows, columns or mines given as well override it. Without
+    /// a size, the board is chosen from a menu.
+    #[structopt(long, possible_values = &["beginner", "intermediate", "expert"])]
+    difficulty: Option<Difficulty>,
+
+    #[structopt(subcommand)]
+    command: Option<Command>,
+}
+
+#[derive(Debug, structopt::StructOpt)]
+enum Command {
+    /// Play games with the built-in solver instead of a person and report
+    /// how often it wins, how many guesses it needs and how long it takes.
+    Simulate {
+        #[structopt(flatten)]
+        game: GameOpt,
+
+        /// The number of games to play. Each game's seed is one more than
+        /// the last, starting from `--seed`.
+        #[structopt(long, default_value = "1000")]
+        games: usize,
+
+        /// The number of games to play at once. Defaults to the number of
+        /// available cores.
+        #[structopt(long)]
+        threads: Option<usize>,
+    },
+
+    /// Time creating a board, exposing its first tile, checking for a win
+    /// and losing on square boards of increasing size. Build with
+    /// `--release` for meaningful numbers.
+    Bench {
+        /// The number of tiles on each board to time, rounded to a square.
+        #[structopt(
+            long,
+            use_delimiter = true,
+            default_value = "1000000,10000000,100000000"
+        )]
+        tiles: Vec<usize>,
+
+        /// The fraction of tiles that hold mines.
+        #[structopt(long, default_value = "0.05")]
+        density: f64,
+
+        /// The seed used to place mines. Chosen randomly if not given.
+        #[structopt(long)]
+        seed: Option<u64>,
+    },
+
+    /// Work with the configuration file.
+    Config(ConfigCommand),
+}
+
+#[derive(Debug, structopt::StructOpt)]
+enum ConfigCommand {
+    /// Print the settings in effect after combining the configuration file
+    /// with the options given, in the form of a configuration file.
+    Show,
+}
+
+impl Opt {
+    /// Take the options not given on the command line from `options`.
+    fn configure(&mut self, options: Options, matches: &ArgMatches) {
+        let given = |name| matches.occurrences_of(name) > 0;
+        configure(
+            &mut self.cell_width,
+            given("cell_width"),
+            options.cell_width.map(Some),
+        );
+        configure(
+            &mut self.cell_height,
+            given("cell_height"),
+            options.cell_height.map(Some),
+        );
+        configure(
+            &mut self.save_board,
+            given("save_board"),
+            options.save_board.clone().map(Some),
+        );
+        configure(
+            &mut self.no_question_marks,
+            given("no_question_marks"),
+            options.no_question_marks,
+        );
+        configure(
+            &mut self.scroll_margin,
+            given("scroll_margin"),
+            options.scroll_margin,
+        );
+        configure(&mut self.render, given("render"), options.render);
+        configure(&mut self.theme, given("theme"), options.theme);
+        configure(&mut self.glyphs, given("glyphs"), options.glyphs);
+        configure(&mut self.keys, given("keys"), options.keys.map(Some));
+        configure(
+            &mut self.keymap,
+            given("keymap"),
+            options.keymap.clone().map(Some),
+        );
+        configure(
+            &mut self.difficulty,
+            given("difficulty"),
+            options.difficulty.map(Some),
+        );
+        if let Some(Command::Simulate { game, .. }) = &mut self.command {
+            if let Some(matches) = matches.subcommand_matches("simulate") {
+                game.configure(options.clone(), matches);
+            }
+        }
+        self.game.configure(options, matches);
+
+        if let Some((rows, columns, mines)) = self.difficulty.map(Difficulty::size) {
+            configure(&mut self.game.rows, given("rows"), Some(rows));
+            configure(&mut self.game.columns, given("columns"), Some(columns));
+            configure(&mut self.game.mines, given("mines"), Some(mines));
+        }
+    }
+
+    fn options(&self) -> Options {
+        Options {
+            cell_width: self.cell_width,
+            cell_height: self.cell_height,
+            save_board: self.save_board.clone(),
+            no_question_marks: Some(self.no_question_marks),
+            scroll_margin: Some(self.scroll_margin),
+            render: Some(self.render),
+            theme: Some(self.theme),
+            glyphs: Some(self.glyphs),
+            keys: self.keys,
+            keymap: self.keymap.clone(),
+            // the rows, columns and mines already hold the difficulty's size
+            ..self.game.options()
+        }
+    }
+}
+
+/// The configuration in `path`, or in the default configuration file if there is one.
+fn load_config(path: Option<&Path>) -> Result<Config> {
+    let (path, required) = match path {
+        Some(path) => (path.to_owned(), true),
+        None => match Config::default_path() {
+            Some(path) => (path, false),
+            None => return Ok(Config::default()),
+        },
+    };
+    match std::fs::read_to_string(&path) {
+        Ok(text) => Ok(Config::parse(&text)
+            .with_context(|| format!("invalid configuration in {path:?}"))?
+            .relative_to(path.parent().unwrap_or(Path::new("")))),
+        Err(e) if !required && e.kind() == std::io::ErrorKind::NotFound => Ok(Config::default()),
+        Err(e) => Err(e).with_context(|| format!("failed to read configuration from {path:?}")),
+    }
 }
 
 fn main() -> Result<()> {
+    let matches = Opt::clap().get_matches();
+    let mut opt = Opt::from_clap(&matches);
+    let config = load_config(opt.config.as_deref())?;
+    let options = config.options(opt.preset.as_deref())?;
+    // choose the board from the menu unless its size was chosen already
+    let sized = options.sizes_board()
+        || ["rows", "columns", "mines", "mask", "difficulty", "board"]
+            .into_iter()
+            .any(|name| matches.occurrences_of(name) > 0);
+    opt.configure(options, &matches);
+
+    let mut keymap_file = if let Some(path) = &opt.keymap {
+        let text = std::fs::read_to_string(path)
+            .with_context(|| format!("failed to read keymap from {path:?}"))?;
+        KeymapFile::parse(&text).with_context(|| format!("invalid keymap in {path:?}"))?
+    } else {
+        KeymapFile::default()
+    };
+    // a keymap file's bindings override the configuration's
+    keymap_file.bindings = config
+        .bindings
+        .into_iter()
+        .chain(keymap_file.bindings)
+        .collect();
+    let keymap = Keymap::from_file(&keymap_file, opt.keys);
+    let effective = opt.options();
+
     let Opt {
-        rows,
-        columns,
-        mines,
+        game,
         cell_width,
         cell_height,
-    } = Opt::from_args();
+        board,
+        save_board,
+        no_question_marks,
+        scroll_margin,
+        render,
+        theme,
+        glyphs,
+        command,
+        ..
+    } = opt;
+
+    match command {
+        Some(Command::Simulate {
+            game,
+            games,
+            threads,
+        }) => {
+            let threads = threads.unwrap_or_else(|| {
+                std::thread::available_parallelism().map_or(1, std::num::NonZeroUsize::get)
+            });
+            let report = simulate::simulate(&game.settings()?, games, threads)
+                .context("simulation failed")?;
+            println!("{report}");
+            return Ok(());
+        }
+        Some(Command::Bench {
+            tiles,
+            density,
+            seed,
+        }) => {
+            let seed = seed.unwrap_or_else(rand::random);
+            println!("seed: {seed}");
+            println!("{}", bench::Timings::HEADER);
+            for tiles in tiles {
+                let timings = bench::bench(tiles, density, seed)
+                    .with_context(|| format!("benchmark of {tiles} tiles failed"))?;
+                println!("{timings}");
+            }
+            return Ok(());
+        }
+        Some(Command::Config(ConfigCommand::Show)) => {
+            let effective = Config {
+                defaults: effective,
+                bindings: keymap.bindings(),
+                ..Config::default()
+            };
+            print!("{}", effective.to_toml()?);
+            return Ok(());
+        }
+        None => {}
+    }
+
+    let settings = game.settings()?;
+    let board = if let Some(path) = board {
+        let layout = std::fs::read_to_string(&path)
+            .with_context(|| format!("failed to read board layout from {path:?}"))?;
+        Some(
+            Board::from_layout(&layout, game.topology())
+                .with_context(|| format!("invalid board layout in {path:?}"))?,
+        )
+    } else if sized {
+        Some(Board::new(settings.clone())?)
+    } else {
+        None
+    };
 
     ui::Ui::builder()
-        .rows(rows)
-        .columns(columns)
-        .mines(mines.min(rows * columns))
+        .board(board)
+        .settings(settings)
         .cell_width(cell_width)
         .cell_height(cell_height)
+        .save_path(save_board)
+        .question_marks(!no_question_marks)
+        .scroll_margin(scroll_margin)
+        .render(render)
+        .theme(theme)
+        .glyphs(glyphs)
+        .keymap(keymap)
         .build()
         .run()
         .context("sweep failed")
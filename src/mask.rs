@@ -0,0 +1,209 @@
+use crate::error::Error;
+use bit_set::BitSet;
+use serde::{Deserialize, Serialize};
+use std::{fmt, str::FromStr};
+
+/// A built-in outline for the tiles of a board.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub(crate) enum Shape {
+    /// Every tile of the rectangle.
+    #[default]
+    Rectangle,
+    /// A diamond touching the middle of each edge.
+    Diamond,
+    /// An ellipse with a hole in the middle.
+    Ring,
+    /// A heart, point down.
+    Heart,
+}
+
+impl Shape {
+    /// Whether the shape covers the point `(x, y)`, where both run from -1 to 1 across the board
+    /// and `y` points up.
+    fn covers(self, x: f64, y: f64) -> bool {
+        match self {
+            Self::Rectangle => true,
+            Self::Diamond => x.abs() + y.abs() <= 1.0,
+            Self::Ring => (0.3..=1.0).contains(&(x * x + y * y)),
+            Self::Heart => {
+                // the classic heart curve, scaled and nudged down to fill the board
+                let (x, y) = (x * 1.15, y * 1.15 + 0.1);
+                (x * x + y * y - 1.0).powi(3) - x * x * y.powi(3) <= 0.0
+            }
+        }
+    }
+}
+
+/// Which tiles of each `rows` by `columns` layer of a board exist.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct Mask {
+    rows: usize,
+    columns: usize,
+    // the row-major indices of the tiles that don't exist
+    gaps: BitSet,
+}
+
+impl Mask {
+    /// A mask covering the tiles inside `shape` stretched over `rows` by `columns` tiles.
+    pub(crate) fn shape(shape: Shape, rows: usize, columns: usize) -> Self {
+        // the centre of a tile, scaled so the board runs from -1 to 1
+        let centre = |index: usize, len: usize| (2 * index + 1) as f64 / len as f64 - 1.0;
+
+        let gaps = (0..rows * columns)
+            .filter(|&index| {
+                let (row, column) = (index / columns, index % columns);
+                !shape.covers(centre(column, columns), -centre(row, rows))
+            })
+            .collect();
+        Self {
+            rows,
+            columns,
+            gaps,
+        }
+    }
+
+    /// Read a mask from text, one line per row of `.` for a tile or `#` for a gap.
+    ///
+    /// Trailing whitespace and blank lines are ignored.
+    pub(crate) fn parse(text: &str) -> Result<Self, Error> {
+        let mut rows = 0;
+        let mut columns = None;
+        let mut gaps = BitSet::new();
+
+        for (i, line) in text.lines().enumerate() {
+            let line = line.trim_end();
+            if line.is_empty() {
+                continue;
+            }
+
+            let length = line.chars().count();
+            let expected = *columns.get_or_insert(length);
+            if length != expected {
+                return Err(Error::RaggedMask {
+                    line: i + 1,
+                    expected,
+                    found: length,
+                });
+            }
+
+            for (j, character) in line.chars().enumerate() {
+                match character {
+                    '.' => {}
+                    '#' => {
+                        gaps.insert(rows * expected + j);
+                    }
+                    _ => {
+                        return Err(Error::ParseMask {
+                            line: i + 1,
+                            column: j + 1,
+                            character,
+                        })
+                    }
+                }
+            }
+            rows += 1;
+        }
+
+        Ok(Self {
+            rows,
+            columns: columns.unwrap_or_default(),
+            gaps,
+        })
+    }
+
+    pub(crate) fn rows(&self) -> usize {
+        self.rows
+    }
+
+    pub(crate) fn columns(&self) -> usize {
+        self.columns
+    }
+
+    /// Whether the tile at `(row, column)` exists.
+    pub(crate) fn contains(&self, (row, column): (usize, usize)) -> bool {
+        row < self.rows && column < self.columns && !self.gaps.contains(row * self.columns + column)
+    }
+
+    /// The number of tiles that exist in a layer.
+    pub(crate) fn tiles(&self) -> usize {
+        self.rows * self.columns - self.gaps.len()
+    }
+}
+
+impl fmt::Display for Shape {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Self::Rectangle => "rectangle",
+            Self::Diamond => "diamond",
+            Self::Ring => "ring",
+            Self::Heart => "heart",
+        })
+    }
+}
+
+impl FromStr for Shape {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "rectangle" => Ok(Self::Rectangle),
+            "diamond" => Ok(Self::Diamond),
+            "ring" => Ok(Self::Ring),
+            "heart" => Ok(Self::Heart),
+            _ => Err(format!(
+                "unknown shape {s:?}, expected `rectangle`, `diamond`, `ring` or `heart`"
+            )),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_tiles_and_gaps() {
+        let mask = Mask::parse(".#.\n...   \n\n").unwrap();
+        assert_eq!((mask.rows(), mask.columns(), mask.tiles()), (2, 3, 5));
+        assert!(!mask.contains((0, 1)));
+        assert!(mask.contains((1, 1)));
+        assert!(!mask.contains((2, 0)));
+    }
+
+    #[test]
+    fn rejects_ragged_rows_and_unknown_characters() {
+        assert!(matches!(
+            Mask::parse("...\n..\n"),
+            Err(Error::RaggedMask {
+                line: 2,
+                expected: 3,
+                found: 2
+            })
+        ));
+        assert!(matches!(
+            Mask::parse("..\n.*\n"),
+            Err(Error::ParseMask {
+                line: 2,
+                column: 2,
+                character: '*'
+            })
+        ));
+    }
+
+    #[test]
+    fn shapes_cover_their_outline() {
+        assert_eq!(Mask::shape(Shape::Rectangle, 4, 7).tiles(), 28);
+
+        let diamond = Mask::shape(Shape::Diamond, 5, 5);
+        assert_eq!(diamond.tiles(), 13);
+        assert!(diamond.contains((2, 0)) && !diamond.contains((0, 0)));
+
+        let ring = Mask::shape(Shape::Ring, 5, 5);
+        assert!(!ring.contains((2, 2)) && ring.contains((2, 0)));
+
+        let heart = Mask::shape(Shape::Heart, 9, 9);
+        assert!(heart.contains((2, 2)) && heart.contains((7, 4)));
+        assert!(!heart.contains((8, 0)));
+    }
+}
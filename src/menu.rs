@@ -0,0 +1,447 @@
+use crate::{
+    error::Error,
+    events::{Event, Events},
+    keymap::{Action, Keymap},
+    mask::Mask,
+    sweep::{Board, Settings},
+    ui::{centered_rect, saturate, Screen},
+};
+use ratatui::{
+    layout::{Alignment, Margin, Rect},
+    style::{Color, Modifier, Style},
+    text::{Line, Span},
+    widgets::{
+        block::{Position, Title},
+        Block, BorderType, Borders, Clear, Paragraph,
+    },
+};
+use serde::{Deserialize, Serialize};
+use std::{
+    fmt,
+    str::FromStr,
+    sync::atomic::{AtomicBool, Ordering},
+};
+use termion::event::{self, Key, MouseButton, MouseEvent};
+
+/// A built-in board size.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub(crate) enum Difficulty {
+    Beginner,
+    Intermediate,
+    Expert,
+}
+
+impl Difficulty {
+    const ALL: [Self; 3] = [Self::Beginner, Self::Intermediate, Self::Expert];
+
+    /// The number of rows, columns and mines.
+    pub(crate) fn size(self) -> (usize, usize, usize) {
+        match self {
+            Self::Beginner => (9, 9, 10),
+            Self::Intermediate => (16, 16, 40),
+            Self::Expert => (16, 30, 99),
+        }
+    }
+}
+
+impl fmt::Display for Difficulty {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Self::Beginner => "beginner",
+            Self::Intermediate => "intermediate",
+            Self::Expert => "expert",
+        })
+    }
+}
+
+impl FromStr for Difficulty {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "beginner" => Ok(Self::Beginner),
+            "intermediate" => Ok(Self::Intermediate),
+            "expert" => Ok(Self::Expert),
+            _ => Err(format!(
+                "unknown difficulty {s:?}, expected `beginner`, `intermediate` or `expert`"
+            )),
+        }
+    }
+}
+
+/// The entry for a custom size comes after the difficulties.
+const CUSTOM: usize = Difficulty::ALL.len();
+
+/// The most digits a custom rows, columns or mines field takes.
+const MAX_DIGITS: u32 = 5;
+
+const FIELDS: [&str; 3] = ["rows", "columns", "mines"];
+
+struct Menu<'settings> {
+    settings: &'settings Settings,
+    /// The highlighted entry, a difficulty or the custom size.
+    selected: usize,
+    /// The custom rows, columns and mines.
+    custom: [usize; 3],
+    /// The custom field being typed into.
+    field: usize,
+    /// Whether the field has been typed into since moving to it, or the first digit replaces it.
+    typed: bool,
+    /// Why the last board asked for couldn't be made.
+    error: Option<String>,
+}
+
+impl<'settings> Menu<'settings> {
+    fn new(settings: &'settings Settings) -> Self {
+        let size = (settings.rows, settings.columns, settings.mines);
+        Self {
+            settings,
+            selected: Difficulty::ALL
+                .iter()
+                .position(|difficulty| difficulty.size() == size)
+                .unwrap_or(CUSTOM),
+            custom: [settings.rows, settings.columns, settings.mines],
+            field: 0,
+            typed: false,
+            error: None,
+        }
+    }
+
+    /// The number of rows, columns and mines of `entry`, as asked for.
+    fn size(&self, entry: usize) -> (usize, usize, usize) {
+        Difficulty::ALL.get(entry).map_or_else(
+            || (self.custom[0], self.custom[1], self.custom[2]),
+            |difficulty| difficulty.size(),
+        )
+    }
+
+    /// The outline of a board of `rows` by `columns` tiles: the one given on the command line
+    /// while the size stays the same, since it was stretched or drawn to that size.
+    fn mask(&self, rows: usize, columns: usize) -> Option<&Mask> {
+        self.settings
+            .mask
+            .as_ref()
+            .filter(|_| (rows, columns) == (self.settings.rows, self.settings.columns))
+    }
+
+    /// The most mines `rows` by `columns` tiles can hold, or why they don't make a board.
+    fn max_mines(&self, rows: usize, columns: usize) -> Result<usize, String> {
+        Settings::check_size(rows, columns).map_err(|e| e.to_string())?;
+        let tiles = self
+            .mask(rows, columns)
+            .map_or(rows * columns, Mask::tiles)
+            .saturating_mul(self.settings.layers);
+        if tiles == 0 {
+            return Err("a board needs at least one row and one column".to_owned());
+        }
+        Ok(Settings::max_mines(tiles, self.settings.mines_per_tile))
+    }
+
+    /// A board of the selected size, with as many of the mines asked for as fit like on the
+    /// command line.
+    fn board(&self) -> Result<Board, String> {
+        let (rows, columns, mines) = self.size(self.selected);
+        let max_mines = self.max_mines(rows, columns)?;
+        Board::new(Settings {
+            rows,
+            columns,
+            mines: mines.min(max_mines),
+            mask: self.mask(rows, columns).cloned(),
+            ..self.settings.clone()
+        })
+        .map_err(|e| e.to_string())
+    }
+
+    fn select(&mut self, entry: usize) {
+        if entry != self.selected {
+            self.selected = entry;
+            self.typed = false;
+        }
+    }
+
+    fn focus(&mut self, field: usize) {
+        if field != self.field {
+            self.field = field;
+            self.typed = false;
+        }
+    }
+
+    /// Type `digit` onto the end of the custom field being typed into.
+    fn type_digit(&mut self, digit: usize) {
+        let value = &mut self.custom[self.field];
+        if !self.typed {
+            *value = 0;
+            self.typed = true;
+        }
+        if *value < 10usize.pow(MAX_DIGITS - 1) {
+            *value = *value * 10 + digit;
+        }
+    }
+
+    fn lines(&self) -> Vec<Line<'_>> {
+        let plain = Style::default();
+        let highlight = Style::default().fg(Color::Black).bg(Color::Cyan);
+        let width = MAX_DIGITS as usize;
+
+        let mut lines = (0..=CUSTOM)
+            .map(|entry| {
+                let selected = entry == self.selected;
+                let style = if selected { highlight } else { plain };
+                let name = Difficulty::ALL
+                    .get(entry)
+                    .map_or_else(|| "custom".to_owned(), ToString::to_string);
+                let (rows, columns, mines) = self.size(entry);
+                // the custom field being typed into stands out from the rest of the line
+                let field_style = |field| {
+                    if entry == CUSTOM && selected && field == self.field {
+                        style.add_modifier(Modifier::REVERSED | Modifier::BOLD)
+                    } else if entry == CUSTOM {
+                        style.add_modifier(Modifier::UNDERLINED)
+                    } else {
+                        style
+                    }
+                };
+                Line::from(vec![
+                    Span::styled(format!(" {name:<13}"), style),
+                    Span::styled(format!("{rows:>width$}"), field_style(0)),
+                    Span::styled(" × ", style),
+                    Span::styled(format!("{columns:<width$}"), field_style(1)),
+                    Span::styled(" ", style),
+                    Span::styled(format!("{mines:>width$}"), field_style(2)),
+                    Span::styled(" mines ", style),
+                ])
+            })
+            .collect::<Vec<_>>();
+
+        lines.push(Line::default());
+        let (rows, columns, mines) = self.size(self.selected);
+        let note = if let Some(error) = &self.error {
+            Span::styled(error.as_str(), Style::default().fg(Color::LightRed))
+        } else {
+            match self.max_mines(rows, columns) {
+                Err(error) => Span::styled(error, Style::default().fg(Color::LightRed)),
+                Ok(max_mines) if mines > max_mines => Span::styled(
+                    format!("only {max_mines} mines fit, so that many are placed"),
+                    Style::default().fg(Color::LightYellow),
+                ),
+                Ok(_) if self.selected == CUSTOM => Span::styled(
+                    format!("type the {}, tab for the next field", FIELDS[self.field]),
+                    Style::default().fg(Color::Gray),
+                ),
+                Ok(_) => Span::raw(""),
+            }
+        };
+        lines.push(Line::from(vec![Span::raw(" "), note]));
+        lines
+    }
+}
+
+/// Let the player choose the size of the next board, from the difficulties or a custom size
+/// based on `settings`. Returns no board if the player quits instead.
+pub(crate) fn choose(
+    terminal: &mut Screen,
+    events: &Events,
+    running: &AtomicBool,
+    keymap: &Keymap,
+    settings: &Settings,
+) -> Result<Option<Board>, Error> {
+    let mut menu = Menu::new(settings);
+    let quit_keys = keymap
+        .keys(Action::Quit)
+        .map(|key| key.to_string())
+        .collect::<Vec<_>>()
+        .join(" ");
+    let controls = if quit_keys.is_empty() {
+        " enter starts ".to_owned()
+    } else {
+        format!(" enter starts · {quit_keys} quits ")
+    };
+    // where the entries were drawn on the last frame, for the mouse to point at
+    let mut entries = Rect::default();
+
+    while running.load(Ordering::SeqCst) {
+        terminal
+            .draw(|frame| {
+                let terminal_rect = frame.size();
+                frame.render_widget(
+                    Block::default()
+                        .borders(Borders::ALL)
+                        .border_type(BorderType::Rounded)
+                        .title(Span::styled(
+                            "Minesweeper",
+                            Style::default()
+                                .fg(Color::LightYellow)
+                                .add_modifier(Modifier::BOLD),
+                        )),
+                    terminal_rect,
+                );
+
+                let lines = menu.lines();
+                // the longest line plus the borders
+                let width = lines
+                    .iter()
+                    .map(Line::width)
+                    .chain(std::iter::once(controls.chars().count()))
+                    .max()
+                    .unwrap_or(0)
+                    + 2;
+                let area = centered_rect(saturate(width), saturate(lines.len() + 2), terminal_rect)
+                    .intersection(terminal_rect);
+                entries = area.inner(&Margin::new(1, 1));
+                entries.height = entries.height.min(saturate(CUSTOM + 1));
+
+                frame.render_widget(Clear, area);
+                frame.render_widget(
+                    Paragraph::new(lines).block(
+                        Block::default()
+                            .borders(Borders::ALL)
+                            .border_type(BorderType::Rounded)
+                            .border_style(Style::default().fg(Color::LightYellow))
+                            .title(Span::styled(
+                                "new game",
+                                Style::default().add_modifier(Modifier::BOLD),
+                            ))
+                            .title(
+                                Title::from(controls.as_str())
+                                    .position(Position::Bottom)
+                                    .alignment(Alignment::Center),
+                            ),
+                    ),
+                    area,
+                );
+            })
+            .map_err(Error::DrawToTerminal)?;
+
+        let custom = menu.selected == CUSTOM;
+        let event = events.next().map_err(Error::GetEvent)?;
+        // any input moves on from the last board that couldn't be made
+        if matches!(event, Event::Input(_)) {
+            menu.error = None;
+        }
+        let action = match event {
+            // typing a custom size comes before the keymap, which may bind digits to moving
+            Event::Input(event::Event::Key(Key::Char(c))) if custom && c.is_ascii_digit() => {
+                menu.type_digit(c.to_digit(10).map_or(0, |digit| digit as usize));
+                continue;
+            }
+            Event::Input(event::Event::Key(Key::Backspace)) if custom => {
+                menu.custom[menu.field] /= 10;
+                menu.typed = true;
+                continue;
+            }
+            Event::Input(event::Event::Key(Key::Char('\t'))) if custom => {
+                menu.focus((menu.field + 1) % FIELDS.len());
+                continue;
+            }
+            Event::Input(event::Event::Key(Key::BackTab)) if custom => {
+                menu.focus((menu.field + FIELDS.len() - 1) % FIELDS.len());
+                continue;
+            }
+            Event::Input(event::Event::Key(Key::Char('\n'))) => Action::Expose,
+            Event::Input(event::Event::Key(key)) => match keymap.action(key) {
+                Some(action) => action,
+                None => continue,
+            },
+            Event::Input(event::Event::Mouse(mouse)) => {
+                let (MouseEvent::Press(_, x, y)
+                | MouseEvent::Release(x, y)
+                | MouseEvent::Hold(x, y)) = mouse;
+                // mouse positions count from one
+                let entry = x
+                    .checked_sub(1 + entries.x)
+                    .filter(|&x| x < entries.width)
+                    .and(y.checked_sub(1 + entries.y))
+                    .filter(|&y| y < entries.height);
+                let Some(entry) = entry else {
+                    continue;
+                };
+                menu.select(usize::from(entry));
+                match mouse {
+                    MouseEvent::Press(MouseButton::Left, ..) => Action::Expose,
+                    _ => continue,
+                }
+            }
+            _ => continue,
+        };
+
+        match action {
+            Action::Up => menu.select(menu.selected.saturating_sub(1)),
+            Action::Down => menu.select((menu.selected + 1).min(CUSTOM)),
+            Action::Left if custom => menu.focus(menu.field.saturating_sub(1)),
+            Action::Right if custom => menu.focus((menu.field + 1).min(FIELDS.len() - 1)),
+            Action::Expose => match menu.board() {
+                Ok(board) => return Ok(Some(board)),
+                Err(error) => menu.error = Some(error),
+            },
+            Action::Quit => break,
+            _ => {}
+        }
+    }
+    Ok(None)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn difficulties_parse_from_their_names() {
+        for difficulty in Difficulty::ALL {
+            assert_eq!(difficulty.to_string().parse(), Ok(difficulty));
+        }
+        assert!("impossible".parse::<Difficulty>().is_err());
+    }
+
+    #[test]
+    fn starts_on_the_difficulty_matching_the_settings() {
+        let settings = Settings::builder()
+            .rows(16)
+            .columns(16)
+            .mines(40)
+            .seed(0)
+            .build();
+        assert_eq!(Menu::new(&settings).selected, 1);
+    }
+
+    #[test]
+    fn typing_replaces_then_extends_a_field() {
+        let settings = Settings::builder()
+            .rows(5)
+            .columns(7)
+            .mines(3)
+            .seed(0)
+            .build();
+        let mut menu = Menu::new(&settings);
+        assert_eq!(menu.selected, CUSTOM);
+
+        menu.focus(1);
+        menu.type_digit(1);
+        menu.type_digit(2);
+        assert_eq!(menu.custom, [5, 12, 3]);
+        for _ in 0..MAX_DIGITS + 2 {
+            menu.type_digit(9);
+        }
+        assert_eq!(menu.custom[1], 12_999);
+    }
+
+    #[test]
+    fn custom_sizes_are_capped() {
+        let settings = Settings::builder()
+            .rows(10)
+            .columns(10)
+            .mines(10)
+            .seed(0)
+            .build();
+        let mut menu = Menu::new(&settings);
+        menu.custom = [Settings::MAX_SIDE + 1, 10, 10];
+        let error = menu.board().err().unwrap();
+        assert!(error.contains(&Settings::MAX_SIDE.to_string()), "{error}");
+
+        menu.custom = [Settings::MAX_SIDE, 0, 10];
+        assert!(menu.board().is_err());
+
+        menu.custom = [3, 3, 20];
+        assert_eq!(menu.board().unwrap().mines(), 8);
+    }
+}
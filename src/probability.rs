@@ -0,0 +1,517 @@
+use crate::{
+    error::Error,
+    sweep::{Board, Coordinate},
+};
+use rand::{Rng, SeedableRng};
+use rand_chacha::ChaCha8Rng;
+use std::collections::{BTreeSet, HashMap};
+
+/// The chance of each tile holding a mine.
+pub(crate) type Probabilities = HashMap<Coordinate, f64>;
+
+/// The most search steps spent enumerating the layouts of one group of frontier tiles before
+/// estimating them by sampling instead.
+const ENUMERATION_LIMIT: usize = 200_000;
+
+/// The number of random layouts sampled for a group of frontier tiles too large to enumerate.
+const SAMPLES: usize = 20_000;
+
+/// Hidden tiles whose mines are tied together by the numbers around them, independent of every
+/// other such group given the number of mines each group holds.
+struct Group {
+    cells: Vec<Coordinate>,
+    // the cells of each constraint, as indices into `cells`, and the mines they hold
+    constraints: Vec<(Vec<usize>, usize)>,
+    // the constraints each cell is part of
+    cell_constraints: Vec<Vec<usize>>,
+}
+
+/// The total weight of the layouts of a group by the number of mines in it, and for each cell
+/// the weight of those layouts putting a mine on the cell.
+struct Tally {
+    weights: Vec<f64>,
+    mined: Vec<Vec<f64>>,
+}
+
+impl Tally {
+    /// An empty tally for the layouts of `group` holding at most `mines` mines, the most left to
+    /// place.
+    fn new(group: &Group, capacity: u8, mines: usize) -> Self {
+        let len = (group.cells.len() * usize::from(capacity)).min(mines) + 1;
+        Self {
+            weights: vec![0.0; len],
+            mined: vec![vec![0.0; group.cells.len()]; len],
+        }
+    }
+
+    /// Count a layout, unless it holds more mines than there are.
+    fn add(&mut self, counts: &[u8], weight: f64) {
+        let mines = counts.iter().copied().map(usize::from).sum::<usize>();
+        let Some(total) = self.weights.get_mut(mines) else {
+            return;
+        };
+        *total += weight;
+        for (mined, &count) in self.mined[mines].iter_mut().zip(counts) {
+            if count > 0 {
+                *mined += weight;
+            }
+        }
+    }
+}
+
+/// The number of ways of choosing `k` of `n` things.
+fn choose(n: u8, k: u8) -> f64 {
+    (0..k).fold(1.0, |ways, i| ways * f64::from(n - i) / f64::from(i + 1))
+}
+
+/// A walk through the layouts of a group, assigning a number of mines to one cell at a time.
+struct Search<'a> {
+    group: &'a Group,
+    capacity: u8,
+    counts: Vec<u8>,
+    // the mines assigned so far to each constraint's cells, and how many of them are unassigned
+    sums: Vec<usize>,
+    unassigned: Vec<usize>,
+    steps: usize,
+}
+
+impl<'a> Search<'a> {
+    fn new(group: &'a Group, capacity: u8) -> Self {
+        Self {
+            group,
+            capacity,
+            counts: vec![0; group.cells.len()],
+            sums: vec![0; group.constraints.len()],
+            unassigned: group
+                .constraints
+                .iter()
+                .map(|(cells, _)| cells.len())
+                .collect(),
+            steps: 0,
+        }
+    }
+
+    /// Put `count` mines on `cell`, returning whether every constraint can still be met.
+    fn assign(&mut self, cell: usize, count: u8) -> bool {
+        self.counts[cell] = count;
+        let capacity = usize::from(self.capacity);
+        let mut feasible = true;
+        for &j in &self.group.cell_constraints[cell] {
+            self.sums[j] += usize::from(count);
+            self.unassigned[j] -= 1;
+            let mines = self.group.constraints[j].1;
+            feasible &=
+                self.sums[j] <= mines && self.sums[j] + self.unassigned[j] * capacity >= mines;
+        }
+        feasible
+    }
+
+    fn unassign(&mut self, cell: usize) {
+        for &j in &self.group.cell_constraints[cell] {
+            self.sums[j] -= usize::from(self.counts[cell]);
+            self.unassigned[j] += 1;
+        }
+        self.counts[cell] = 0;
+    }
+
+    /// Visit every layout of the cells from `cell` on, giving up once over the step limit.
+    fn enumerate(&mut self, cell: usize, weight: f64, tally: &mut Tally) -> bool {
+        self.steps += 1;
+        if self.steps > ENUMERATION_LIMIT {
+            return false;
+        }
+        if cell == self.counts.len() {
+            tally.add(&self.counts, weight);
+            return true;
+        }
+
+        for count in 0..=self.capacity {
+            let feasible = self.assign(cell, count);
+            let finished =
+                !feasible || self.enumerate(cell + 1, weight * choose(self.capacity, count), tally);
+            self.unassign(cell);
+            if !finished {
+                return false;
+            }
+        }
+        true
+    }
+
+    /// Walk one random path to a layout, choosing uniformly between the counts that keep the
+    /// constraints satisfiable, and tally the layout weighted by the inverse chance of taking
+    /// that path so the tallies estimate the enumerated ones.
+    fn sample(&mut self, rng: &mut impl Rng, tally: &mut Tally) {
+        let mut weight = 1.0;
+        let mut assigned = 0;
+        while assigned < self.counts.len() {
+            let feasible = (0..=self.capacity)
+                .filter(|&count| {
+                    let feasible = self.assign(assigned, count);
+                    self.unassign(assigned);
+                    feasible
+                })
+                .collect::<Vec<_>>();
+            if feasible.is_empty() {
+                break;
+            }
+
+            let count = feasible[rng.gen_range(0..feasible.len())];
+            self.assign(assigned, count);
+            weight *= feasible.len() as f64 * choose(self.capacity, count);
+            assigned += 1;
+        }
+
+        if assigned == self.counts.len() {
+            tally.add(&self.counts, weight);
+        }
+        for cell in 0..assigned {
+            self.unassign(cell);
+        }
+    }
+}
+
+/// Split the hidden tiles next to exposed numbers into groups linked by shared numbers,
+/// returning the groups and the remaining hidden tiles.
+fn groups(board: &Board) -> Result<(Vec<Group>, Vec<Coordinate>), Error> {
+    let mut constraints = Vec::new();
+    let mut hidden = BTreeSet::new();
+
+    for coord in board.coordinates() {
+        let tile = board.tile(coord)?;
+        if !(tile.exposed || tile.flagged()) {
+            hidden.insert(coord);
+            continue;
+        } else if !tile.exposed {
+            continue;
+        }
+
+        let mut cells = Vec::new();
+        let mut flags = 0;
+        for neighbour in board.adjacent(coord) {
+            let neighbour_tile = board.tile(neighbour)?;
+            if neighbour_tile.flagged() {
+                flags += usize::from(neighbour_tile.flags());
+            } else if !neighbour_tile.exposed {
+                cells.push(neighbour);
+            }
+        }
+        if !cells.is_empty() {
+            // flags beyond the number make the board contradictory, which no layout satisfies
+            let mines = usize::from(board.adjacent_mines(coord)?)
+                .checked_sub(flags)
+                .unwrap_or(usize::MAX);
+            constraints.push((cells, mines));
+        }
+    }
+
+    // join constraints sharing a cell, walking outwards from each unvisited constraint
+    let mut by_cell = HashMap::<Coordinate, Vec<usize>>::new();
+    for (i, (cells, _)) in constraints.iter().enumerate() {
+        for &cell in cells {
+            by_cell.entry(cell).or_default().push(i);
+        }
+    }
+
+    let mut visited = vec![false; constraints.len()];
+    let mut groups = Vec::new();
+    for start in 0..constraints.len() {
+        if visited[start] {
+            continue;
+        }
+        visited[start] = true;
+
+        let mut members = vec![start];
+        let mut cells = Vec::new();
+        let mut indices = HashMap::new();
+        let mut next = 0;
+        while let Some(&i) = members.get(next) {
+            next += 1;
+            for &cell in &constraints[i].0 {
+                if indices.contains_key(&cell) {
+                    continue;
+                }
+                indices.insert(cell, cells.len());
+                cells.push(cell);
+                for &j in &by_cell[&cell] {
+                    if !visited[j] {
+                        visited[j] = true;
+                        members.push(j);
+                    }
+                }
+            }
+        }
+
+        let group_constraints = members
+            .iter()
+            .map(|&i| {
+                let (cells, mines) = &constraints[i];
+                (cells.iter().map(|cell| indices[cell]).collect(), *mines)
+            })
+            .collect::<Vec<(Vec<usize>, usize)>>();
+        let mut cell_constraints = vec![Vec::new(); cells.len()];
+        for (j, (constraint_cells, _)) in group_constraints.iter().enumerate() {
+            for &cell in constraint_cells {
+                cell_constraints[cell].push(j);
+            }
+        }
+        for cell in &cells {
+            hidden.remove(cell);
+        }
+
+        groups.push(Group {
+            cells,
+            constraints: group_constraints,
+            cell_constraints,
+        });
+    }
+
+    Ok((groups, hidden.into_iter().collect()))
+}
+
+/// The weights of each total number of mines across two independent sets of tiles.
+fn convolve(left: &[f64], right: &[f64]) -> Vec<f64> {
+    let mut total = vec![0.0; left.len() + right.len() - 1];
+    for (i, &l) in left.iter().enumerate() {
+        for (j, &r) in right.iter().enumerate() {
+            total[i + j] += l * r;
+        }
+    }
+    // only the ratios matter, so keep the weights from overflowing
+    let max = total.iter().copied().fold(0.0, f64::max);
+    if max > 0.0 {
+        total.iter_mut().for_each(|weight| *weight /= max);
+    }
+    total
+}
+
+/// The chance of each hidden, unflagged tile holding at least one mine, given only what the
+/// player can see: the exposed numbers, the flags and the total number of mines.
+///
+/// Before the mines are placed, whichever tile is exposed first is kept clear of them, so every
+/// tile is safe.
+///
+/// Flags are trusted to mark exactly the mines on their tiles. The hidden tiles next to exposed
+/// numbers are split into independent groups, and every layout of each group is weighed by the
+/// number of ways of placing the rest of the mines on the other hidden tiles. Groups with too
+/// many layouts to enumerate are estimated by sampling instead. A board whose visible state no
+/// layout satisfies, or whose game is over, has no probabilities.
+pub(crate) fn probabilities(board: &Board) -> Result<Probabilities, Error> {
+    let mut probabilities = HashMap::new();
+    if board.state().is_over() {
+        return Ok(probabilities);
+    }
+    if !board.started() {
+        for coord in board.coordinates() {
+            if !board.tile(coord)?.flagged() {
+                probabilities.insert(coord, 0.0);
+            }
+        }
+        return Ok(probabilities);
+    }
+
+    let capacity = board.mines_per_tile();
+    // the mines not accounted for by flags
+    let mines = board.available_flags();
+
+    let (groups, interior) = groups(board)?;
+    // a fixed seed keeps the estimates from flickering between redraws
+    let mut rng = ChaCha8Rng::seed_from_u64(0);
+    let tallies = groups
+        .iter()
+        .map(|group| {
+            let mut tally = Tally::new(group, capacity, mines);
+            if !Search::new(group, capacity).enumerate(0, 1.0, &mut tally) {
+                tally = Tally::new(group, capacity, mines);
+                let mut search = Search::new(group, capacity);
+                for _ in 0..SAMPLES {
+                    search.sample(&mut rng, &mut tally);
+                }
+            }
+            tally
+        })
+        .collect::<Vec<_>>();
+
+    // the relative number of ways of placing `r` mines on the slots of the interior tiles, in
+    // logarithms until scaled to avoid overflowing
+    let slots = interior.len() * usize::from(capacity);
+    let mut ln_ways = vec![0.0; mines.min(slots) + 1];
+    for r in 1..ln_ways.len() {
+        ln_ways[r] = ln_ways[r - 1] + ((slots - r + 1) as f64 / r as f64).ln();
+    }
+    let max_ln_ways = ln_ways.iter().copied().fold(f64::MIN, f64::max);
+    let interior_ways = ln_ways
+        .iter()
+        .map(|ln| (ln - max_ln_ways).exp())
+        .collect::<Vec<_>>();
+    let interior_weight = |frontier_mines: usize| {
+        mines
+            .checked_sub(frontier_mines)
+            .and_then(|r| interior_ways.get(r))
+            .copied()
+            .unwrap_or(0.0)
+    };
+
+    // the weights of every other group's mine counts combined, from prefix and suffix products
+    let mut prefixes = vec![vec![1.0]];
+    for tally in &tallies {
+        prefixes.push(convolve(prefixes.last().unwrap(), &tally.weights));
+    }
+    let mut suffixes = vec![vec![1.0]];
+    for tally in tallies.iter().rev() {
+        suffixes.push(convolve(suffixes.last().unwrap(), &tally.weights));
+    }
+    suffixes.reverse();
+
+    for (i, (group, tally)) in groups.iter().zip(&tallies).enumerate() {
+        let others = convolve(&prefixes[i], &suffixes[i + 1]);
+        let rest = (0..tally.weights.len())
+            .map(|k| {
+                others
+                    .iter()
+                    .enumerate()
+                    .map(|(t, weight)| weight * interior_weight(k + t))
+                    .sum::<f64>()
+            })
+            .collect::<Vec<_>>();
+
+        let total = tally
+            .weights
+            .iter()
+            .zip(&rest)
+            .map(|(weight, rest)| weight * rest)
+            .sum::<f64>();
+        if total <= 0.0 {
+            return Ok(HashMap::new());
+        }
+        for (cell, &coord) in group.cells.iter().enumerate() {
+            let mined = tally
+                .mined
+                .iter()
+                .zip(&rest)
+                .map(|(mined, rest)| mined[cell] * rest)
+                .sum::<f64>();
+            probabilities.insert(coord, mined / total);
+        }
+    }
+
+    if !interior.is_empty() {
+        let frontier = prefixes.last().unwrap();
+        let mut total = 0.0;
+        let mut mined = 0.0;
+        for (t, weight) in frontier.iter().enumerate() {
+            let Some(r) = mines.checked_sub(t).filter(|&r| r <= slots) else {
+                continue;
+            };
+            let weight = weight * interior_ways[r];
+            // the chance that none of the `r` mines land on one tile's slots
+            let empty = (0..usize::from(capacity))
+                .map(|i| (slots - r).saturating_sub(i) as f64 / (slots - i) as f64)
+                .product::<f64>();
+            total += weight;
+            mined += weight * (1.0 - empty);
+        }
+        if total <= 0.0 {
+            return Ok(HashMap::new());
+        }
+        for coord in interior {
+            probabilities.insert(coord, mined / total);
+        }
+    }
+
+    Ok(probabilities)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{sweep::Settings, topology::Topology};
+    use std::rc::Rc;
+
+    fn assert_close(actual: f64, expected: f64) {
+        assert!((actual - expected).abs() < 1e-9, "{actual} != {expected}");
+    }
+
+    #[test]
+    fn splits_a_number_evenly_between_its_tiles() {
+        let board = Board::from_layout("o.\n.*\n", Topology::default()).unwrap();
+        let probabilities = probabilities(&board).unwrap();
+        assert_eq!(probabilities.len(), 3);
+        for coord in [(0, 0, 1), (0, 1, 0), (0, 1, 1)] {
+            assert_close(probabilities[&coord], 1.0 / 3.0);
+        }
+    }
+
+    #[test]
+    fn clears_the_interior_once_the_frontier_holds_every_mine() {
+        let board = Board::from_layout("*o..\n", Topology::default()).unwrap();
+        let probabilities = probabilities(&board).unwrap();
+        assert_close(probabilities[&(0, 0, 0)], 0.5);
+        assert_close(probabilities[&(0, 0, 2)], 0.5);
+        assert_close(probabilities[&(0, 0, 3)], 0.0);
+    }
+
+    #[test]
+    fn weighs_layouts_by_the_mines_left_for_the_interior() {
+        // one mine beside the 1 leaves one for the three tiles beyond it, while none beside it
+        // can't satisfy the 1, so the interior tiles share a single mine
+        let board = Board::from_layout("o..**\n", Topology::default()).unwrap();
+        let probabilities = probabilities(&board).unwrap();
+        assert_close(probabilities[&(0, 0, 1)], 0.0);
+        for column in 2..5 {
+            assert_close(probabilities[&(0, 0, column)], 2.0 / 3.0);
+        }
+    }
+
+    #[test]
+    fn counts_every_mine_on_multi_mine_tiles() {
+        let board = Board::from_layout("o2.\n", Topology::default()).unwrap();
+        let probabilities = probabilities(&board).unwrap();
+        assert_close(probabilities[&(0, 0, 1)], 1.0);
+        assert_close(probabilities[&(0, 0, 2)], 0.0);
+    }
+
+    #[test]
+    fn leaves_out_flagged_tiles() {
+        let board = Board::from_layout("oF.\n", Topology::default()).unwrap();
+        let probabilities = probabilities(&board).unwrap();
+        assert!(!probabilities.contains_key(&(0, 0, 1)));
+        assert_close(probabilities[&(0, 0, 2)], 0.0);
+    }
+
+    #[test]
+    fn every_tile_is_safe_before_the_mines_are_placed() {
+        let board = Board::new(
+            Settings::builder()
+                .rows(4)
+                .columns(4)
+                .mines(15)
+                .seed(0)
+                .build(),
+        )
+        .unwrap();
+        let probabilities = probabilities(&board).unwrap();
+        assert_eq!(probabilities.len(), 16);
+        assert!(probabilities.values().all(|&p| p == 0.0));
+    }
+
+    #[test]
+    fn tallies_no_more_mines_than_are_left() {
+        let board = Board::from_layout("o.\n.*\n", Topology::default()).unwrap();
+        let (groups, interior) = groups(&board).unwrap();
+        assert!(interior.is_empty());
+        let tally = Tally::new(&groups[0], 1, board.available_flags());
+        assert_eq!(tally.weights.len(), 2);
+    }
+
+    #[test]
+    fn probabilities_are_cached_until_the_board_changes() {
+        let mut board = Board::from_layout("o..\n..*\n", Topology::default()).unwrap();
+        let first = board.mine_probabilities().unwrap();
+        assert!(Rc::ptr_eq(&first, &board.mine_probabilities().unwrap()));
+
+        board.flag((0, 1, 2)).unwrap();
+        let flagged = board.mine_probabilities().unwrap();
+        assert!(!Rc::ptr_eq(&first, &flagged));
+        assert!(!flagged.contains_key(&(0, 1, 2)));
+    }
+}
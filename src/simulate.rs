@@ -0,0 +1,207 @@
+use crate::{
+    error::Error,
+    solver,
+    sweep::{Board, Coordinate, GameState, Settings},
+};
+use std::{
+    fmt, panic,
+    sync::atomic::{AtomicUsize, Ordering},
+    thread,
+    time::{Duration, Instant},
+};
+
+/// The tile closest to the middle of the board.
+fn middle(board: &Board) -> Option<Coordinate> {
+    let (layer, row, column) = (board.layers / 2, board.rows / 2, board.columns / 2);
+    board
+        .coordinates()
+        .min_by_key(|&(l, r, c)| l.abs_diff(layer) + r.abs_diff(row) + c.abs_diff(column))
+}
+
+/// The hidden tile least likely to be a mine, the first in reading order on ties so that games
+/// replay exactly.
+fn safest(board: &Board) -> Result<Option<Coordinate>, Error> {
+    Ok(board
+        .mine_probabilities()?
+        .iter()
+        .min_by(|(a, p), (b, q)| p.total_cmp(q).then_with(|| a.cmp(b)))
+        .map(|(&coord, _)| coord))
+}
+
+/// Play `board` to the end from what a player could see, exposing the middle tile first, then
+/// making every certain move and guessing the tile least likely to be a mine when there is
+/// none.
+///
+/// Returns the number of guesses made, not counting the first expose.
+pub(crate) fn play(board: &mut Board) -> Result<usize, Error> {
+    let Some(start) = middle(board) else {
+        return Ok(0);
+    };
+    board.expose(start)?;
+
+    let mut guesses = 0;
+    while board.state() == GameState::Playing {
+        let deductions = solver::deduce(board)?;
+        if !deductions.is_empty() {
+            solver::apply(board, deductions)?;
+        } else if let Some(coord) = safest(board)? {
+            guesses += 1;
+            board.expose(coord)?;
+        } else {
+            // the visible state contradicts itself, which only a wrong flag can cause
+            break;
+        }
+    }
+    Ok(guesses)
+}
+
+/// How the bot fared over a number of games.
+#[derive(Debug, Default, Clone, Copy)]
+pub(crate) struct Report {
+    pub(crate) games: usize,
+    pub(crate) wins: usize,
+    pub(crate) guesses: usize,
+    /// The time spent generating and playing games, summed over every thread.
+    pub(crate) time: Duration,
+    /// The time the whole simulation took.
+    pub(crate) wall_time: Duration,
+    pub(crate) threads: usize,
+    /// The seed of the first game, each later game adding one to it.
+    pub(crate) seed: u64,
+}
+
+impl Report {
+    fn merge(mut self, other: Self) -> Self {
+        self.games += other.games;
+        self.wins += other.wins;
+        self.guesses += other.guesses;
+        self.time += other.time;
+        self
+    }
+}
+
+impl fmt::Display for Report {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        // avoid dividing by zero when no games were played
+        let games = self.games.max(1) as f64;
+        writeln!(f, "games:        {} (seed: {})", self.games, self.seed)?;
+        writeln!(
+            f,
+            "win rate:     {:.2}% ({} won)",
+            100.0 * self.wins as f64 / games,
+            self.wins
+        )?;
+        writeln!(f, "guesses/game: {:.3}", self.guesses as f64 / games)?;
+        writeln!(f, "time/game:    {:.3?}", self.time.div_f64(games))?;
+        write!(
+            f,
+            "wall time:    {:.3?} on {} thread{}",
+            self.wall_time,
+            self.threads,
+            if self.threads == 1 { "" } else { "s" }
+        )
+    }
+}
+
+/// Play `games` games with the bot on boards built from `settings`, spread over `threads`
+/// threads.
+///
+/// Game `i` uses the seed of `settings` plus `i`, so the results don't depend on the number of
+/// threads.
+pub(crate) fn simulate(settings: &Settings, games: usize, threads: usize) -> Result<Report, Error> {
+    let threads = threads.max(1);
+    let next = AtomicUsize::new(0);
+
+    let worker = || -> Result<Report, Error> {
+        let mut report = Report::default();
+        loop {
+            let game = next.fetch_add(1, Ordering::Relaxed);
+            if game >= games {
+                return Ok(report);
+            }
+
+            let started = Instant::now();
+            let result = Board::new(Settings {
+                seed: settings.seed.wrapping_add(game as u64),
+                ..settings.clone()
+            })
+            .and_then(|mut board| Ok((play(&mut board)?, board.state())));
+            let (guesses, state) = match result {
+                Ok(outcome) => outcome,
+                Err(e) => {
+                    // stop the other threads from starting any more games
+                    next.store(games, Ordering::Relaxed);
+                    return Err(e);
+                }
+            };
+
+            report.games += 1;
+            report.wins += usize::from(state == GameState::Won);
+            report.guesses += guesses;
+            report.time += started.elapsed();
+        }
+    };
+
+    let started = Instant::now();
+    let reports = thread::scope(|scope| {
+        let handles = (0..threads)
+            .map(|_| scope.spawn(worker))
+            .collect::<Vec<_>>();
+        handles
+            .into_iter()
+            .map(|handle| handle.join().unwrap_or_else(|e| panic::resume_unwind(e)))
+            .collect::<Result<Vec<_>, _>>()
+    })?;
+
+    Ok(Report {
+        wall_time: started.elapsed(),
+        threads,
+        seed: settings.seed,
+        ..reports.into_iter().fold(Report::default(), Report::merge)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sweep::Generator;
+
+    #[test]
+    fn results_do_not_depend_on_the_threads() {
+        let settings = Settings::builder()
+            .rows(9)
+            .columns(9)
+            .mines(10)
+            .seed(11)
+            .build();
+        let one = simulate(&settings, 20, 1).unwrap();
+        let four = simulate(&settings, 20, 4).unwrap();
+        assert_eq!(
+            (one.games, one.wins, one.guesses),
+            (four.games, four.wins, four.guesses)
+        );
+        assert_eq!(one.games, 20);
+        assert_eq!((one.seed, four.threads), (11, 4));
+    }
+
+    #[test]
+    fn no_guess_boards_are_won_without_guessing() {
+        let settings = Settings::builder()
+            .rows(8)
+            .columns(8)
+            .mines(10)
+            .generator(Generator::NoGuess { attempts: 1000 })
+            .seed(4)
+            .build();
+        let report = simulate(&settings, 5, 2).unwrap();
+        assert_eq!((report.wins, report.guesses), (5, 0));
+    }
+
+    #[test]
+    fn play_follows_certain_moves_to_the_end() {
+        // the middle tile opens the whole board but the corner holding the mine
+        let mut board = Board::from_layout("...*\n....\n....\n....\n", Default::default()).unwrap();
+        assert_eq!(play(&mut board).unwrap(), 0);
+        assert_eq!(board.state(), GameState::Won);
+    }
+}
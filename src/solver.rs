@@ -0,0 +1,380 @@
+use crate::{
+    error::Error,
+    sweep::{Board, Coordinate, GameState},
+};
+use std::collections::{BTreeSet, HashMap};
+
+/// A move that is certain given only the visible state of a board.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct Deduction {
+    pub(crate) coord: Coordinate,
+    /// The number of mines the tile certainly holds, none if it is certainly safe.
+    pub(crate) mines: u8,
+    /// The exposed tiles whose numbers justify the deduction.
+    pub(crate) reasons: Vec<Coordinate>,
+}
+
+/// The hidden, unflagged `cells` that together hold exactly `mines` mines.
+///
+/// Flags are trusted to mark exactly the mines on their tiles.
+#[derive(Debug)]
+struct Constraint {
+    cells: BTreeSet<Coordinate>,
+    mines: usize,
+    // the exposed tile the constraint was read from, if any
+    source: Option<Coordinate>,
+}
+
+impl Constraint {
+    fn reasons(&self) -> impl Iterator<Item = Coordinate> {
+        self.source.into_iter()
+    }
+}
+
+fn is_hidden(board: &Board, coord: Coordinate) -> Result<bool, Error> {
+    let tile = board.tile(coord)?;
+    Ok(!(tile.exposed || tile.flagged()))
+}
+
+/// Read one constraint from each exposed number bordering hidden tiles, plus one for the total
+/// number of mines left.
+fn constraints(board: &Board) -> Result<Vec<Constraint>, Error> {
+    let mut constraints = Vec::new();
+    let mut hidden = BTreeSet::new();
+    let mut flagged = 0;
+    let capacity = usize::from(board.mines_per_tile());
+
+    for coord in board.coordinates() {
+        let tile = board.tile(coord)?;
+        if tile.flagged() {
+            flagged += usize::from(tile.flags());
+            continue;
+        } else if !tile.exposed {
+            hidden.insert(coord);
+            continue;
+        }
+
+        let mut cells = BTreeSet::new();
+        let mut flagged_neighbours = 0;
+        for neighbour in board.adjacent(coord) {
+            let neighbour_tile = board.tile(neighbour)?;
+            if neighbour_tile.flagged() {
+                flagged_neighbours += usize::from(neighbour_tile.flags());
+            } else if is_hidden(board, neighbour)? {
+                cells.insert(neighbour);
+            }
+        }
+
+        // a number contradicted by the flags around it carries no usable information
+        if let Some(mines) =
+            usize::from(board.adjacent_mines(coord)?).checked_sub(flagged_neighbours)
+        {
+            if !cells.is_empty() && mines <= cells.len() * capacity {
+                constraints.push(Constraint {
+                    cells,
+                    mines,
+                    source: Some(coord),
+                });
+            }
+        }
+    }
+
+    if let Some(mines) = board.mines().checked_sub(flagged) {
+        if !hidden.is_empty() && mines <= hidden.len() * capacity {
+            constraints.push(Constraint {
+                cells: hidden,
+                mines,
+                source: None,
+            });
+        }
+    }
+
+    Ok(constraints)
+}
+
+/// Record that every cell in `cells` certainly holds `mines` mines, keeping the first
+/// justification found for each.
+fn conclude<'a>(
+    deductions: &mut HashMap<Coordinate, Deduction>,
+    cells: impl IntoIterator<Item = &'a Coordinate>,
+    mines: u8,
+    reasons: Vec<Coordinate>,
+) {
+    for &coord in cells {
+        deductions.entry(coord).or_insert_with(|| Deduction {
+            coord,
+            mines,
+            reasons: reasons.clone(),
+        });
+    }
+}
+
+/// Record what knowing that `cells` hold exactly `mines` mines between them settles: that none
+/// of them hold any, that all of them hold as many as a tile can, or how many the only cell
+/// holds.
+fn settle(
+    deductions: &mut HashMap<Coordinate, Deduction>,
+    cells: Vec<&Coordinate>,
+    mines: usize,
+    capacity: u8,
+    reasons: Vec<Coordinate>,
+) {
+    let mines = if mines == 0 {
+        0
+    } else if mines == cells.len() * usize::from(capacity) {
+        capacity
+    } else if let (1, Ok(mines)) = (cells.len(), u8::try_from(mines)) {
+        mines
+    } else {
+        return;
+    };
+    conclude(deductions, cells, mines, reasons);
+}
+
+/// Find every tile that is certainly safe or certainly a mine given the visible state of `board`.
+///
+/// Each number is a constraint on its hidden neighbours. A constraint that needs no more mines
+/// clears its cells, one that needs as many as they can hold fills them and one on a single
+/// cell counts its mines. When one constraint's cells are a subset of another's, the difference
+/// of the two is itself such a constraint.
+pub(crate) fn deduce(board: &Board) -> Result<Vec<Deduction>, Error> {
+    let constraints = constraints(board)?;
+    let capacity = board.mines_per_tile();
+    let mut deductions = HashMap::new();
+
+    // index constraints by the cells they cover, to only compare constraints that overlap
+    let mut by_cell = HashMap::<Coordinate, Vec<usize>>::new();
+    for (i, constraint) in constraints.iter().enumerate() {
+        for &cell in &constraint.cells {
+            by_cell.entry(cell).or_default().push(i);
+        }
+    }
+
+    for constraint in &constraints {
+        settle(
+            &mut deductions,
+            constraint.cells.iter().collect(),
+            constraint.mines,
+            capacity,
+            constraint.reasons().collect(),
+        );
+    }
+
+    for subset in &constraints {
+        let Some(first) = subset.cells.first() else {
+            continue;
+        };
+
+        for &j in &by_cell[first] {
+            let superset = &constraints[j];
+            if superset.cells.len() <= subset.cells.len()
+                || !subset.cells.is_subset(&superset.cells)
+            {
+                continue;
+            }
+
+            let Some(mines) = superset.mines.checked_sub(subset.mines) else {
+                continue;
+            };
+            let difference = superset.cells.difference(&subset.cells).collect::<Vec<_>>();
+            let reasons = subset
+                .reasons()
+                .chain(superset.reasons())
+                .collect::<Vec<_>>();
+
+            settle(&mut deductions, difference, mines, capacity, reasons);
+        }
+    }
+
+    let mut deductions = deductions.into_values().collect::<Vec<_>>();
+    deductions.sort_by_key(|deduction| deduction.coord);
+    Ok(deductions)
+}
+
+/// Like [`deduce`], but reading only the exposed numbers and the total number of mines, so that a
+/// wrongly placed flag can't lead to a wrong deduction. Mines already flagged as such are left
+/// out.
+pub(crate) fn deduce_from_numbers(board: &Board) -> Result<Vec<Deduction>, Error> {
+    let mut deductions = Vec::new();
+    for deduction in deduce(&board.unmarked())? {
+        if deduction.mines == 0 || board.tile(deduction.coord)?.flags() != deduction.mines {
+            deductions.push(deduction);
+        }
+    }
+    Ok(deductions)
+}
+
+/// Whether `board` can be cleared from `start` by applying deductions alone.
+pub(crate) fn solvable(board: &Board, start: Coordinate) -> Result<bool, Error> {
+    // the player's marks may be wrong, so start from the numbers alone
+    let mut board = board.unmarked();
+
+    board.expose(start)?;
+
+    while board.state() == GameState::Playing {
+        let deductions = deduce(&board)?;
+        if deductions.is_empty() {
+            return Ok(false);
+        }
+        apply(&mut board, deductions)?;
+    }
+
+    Ok(board.state() == GameState::Won)
+}
+
+/// Expose every tile deduced to be safe and flag every tile deduced to hold mines, stopping
+/// early if the game ends.
+pub(crate) fn apply(board: &mut Board, deductions: Vec<Deduction>) -> Result<(), Error> {
+    for Deduction { coord, mines, .. } in deductions {
+        if mines > 0 {
+            for _ in 0..mines {
+                board.flag(coord)?;
+            }
+        } else {
+            board.expose(coord)?;
+        }
+
+        if board.state().is_over() {
+            break;
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        sweep::{GameState, Generator, Settings},
+        topology::Topology,
+    };
+
+    #[test]
+    fn deductions_agree_with_the_hidden_mines() {
+        for (seed, mines_per_tile) in (0..20).zip([1, 2].into_iter().cycle()) {
+            let mut board = Board::new(
+                Settings::builder()
+                    .rows(9)
+                    .columns(9)
+                    .mines(10)
+                    .mines_per_tile(mines_per_tile)
+                    .seed(seed)
+                    .build(),
+            )
+            .unwrap();
+            board.expose((0, 4, 4)).unwrap();
+
+            while board.state() == GameState::Playing {
+                let deductions = deduce(&board).unwrap();
+                if deductions.is_empty() {
+                    break;
+                }
+                for Deduction { coord, mines, .. } in deductions {
+                    assert_eq!(board.tile(coord).unwrap().mines, mines, "at {coord:?}");
+                    // the last safe tile wins the game before the rest are applied
+                    if board.state().is_over() {
+                        continue;
+                    }
+                    if mines > 0 {
+                        for _ in 0..mines {
+                            board.flag(coord).unwrap();
+                        }
+                    } else {
+                        board.expose(coord).unwrap();
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn deduces_safe_tiles_and_mines_from_numbers_and_the_mine_count() {
+        let board = Board::from_layout("o.*\n", Topology::default()).unwrap();
+        assert_eq!(
+            deduce(&board).unwrap(),
+            vec![
+                Deduction {
+                    coord: (0, 0, 1),
+                    mines: 0,
+                    reasons: vec![(0, 0, 0)],
+                },
+                Deduction {
+                    coord: (0, 0, 2),
+                    mines: 1,
+                    reasons: vec![(0, 0, 0)],
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn deduces_from_one_number_covering_part_of_another() {
+        // the 1 at the top left covers two of the three tiles below the 2 beside it, so the
+        // third holds the 2's other mine
+        let board = Board::from_layout("ooo\n*.*\n", Topology::default()).unwrap();
+        let deductions = deduce(&board).unwrap();
+        assert!(deductions.contains(&Deduction {
+            coord: (0, 1, 2),
+            mines: 1,
+            reasons: vec![(0, 0, 0), (0, 0, 1)],
+        }));
+    }
+
+    #[test]
+    fn deductions_from_numbers_ignore_wrong_flags() {
+        // trusting the flag would clear the mine on the left
+        let board = Board::from_layout("*of.\n", Topology::default()).unwrap();
+        assert_eq!(
+            deduce_from_numbers(&board).unwrap(),
+            vec![Deduction {
+                coord: (0, 0, 3),
+                mines: 0,
+                reasons: vec![(0, 0, 1)],
+            }]
+        );
+    }
+
+    #[test]
+    fn deductions_from_numbers_skip_flagged_mines() {
+        let board = Board::from_layout("oFo.\n", Topology::default()).unwrap();
+        assert_eq!(
+            deduce_from_numbers(&board).unwrap(),
+            vec![Deduction {
+                coord: (0, 0, 3),
+                mines: 0,
+                reasons: vec![(0, 0, 0), (0, 0, 2)],
+            }]
+        );
+    }
+
+    #[test]
+    fn finds_nothing_in_a_fifty_fifty() {
+        let board = Board::from_layout("*o.\n", Topology::default()).unwrap();
+        assert!(deduce(&board).unwrap().is_empty());
+        assert!(!solvable(&board, (0, 0, 1)).unwrap());
+    }
+
+    #[test]
+    fn solves_a_board_that_opens_from_the_start() {
+        let board = Board::from_layout("..*\n", Topology::default()).unwrap();
+        assert!(solvable(&board, (0, 0, 0)).unwrap());
+    }
+
+    #[test]
+    fn no_guess_boards_can_be_solved_from_the_first_expose() {
+        for seed in 0..20 {
+            let mut board = Board::new(
+                Settings::builder()
+                    .rows(6)
+                    .columns(6)
+                    .mines(6)
+                    .seed(seed)
+                    .generator(Generator::NoGuess { attempts: 1000 })
+                    .build(),
+            )
+            .unwrap();
+            board.expose((0, 0, 0)).unwrap();
+            assert!(solvable(&board, (0, 0, 0)).unwrap());
+        }
+    }
+}
@@ -1,209 +1,2069 @@
-use crate::error::Error;
+use crate::{
+    error::Error,
+    mask::Mask,
+    probability::{self, Probabilities},
+    solver,
+    topology::Topology,
+};
 use bit_set::BitSet;
-use std::collections::VecDeque;
+use rand::{RngCore, SeedableRng};
+use rand_chacha::ChaCha8Rng;
+use std::{cell::RefCell, collections::HashMap, rc::Rc};
 
-pub(crate) type Coordinate = (usize, usize);
+/// A tile's layer, row and column.
+pub(crate) type Coordinate = (usize, usize, usize);
 
-#[derive(Debug)]
+/// A player's annotation on a hidden tile.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub(crate) enum Mark {
+    #[default]
+    None,
+    /// The player is sure the tile holds this many mines, at least one.
+    Flag(u8),
+    /// The player suspects the tile is a mine.
+    Question,
+}
+
+/// A single tile, as read from a [`Board`].
+#[derive(Debug, Default, Clone, Copy)]
 pub(crate) struct Tile {
-    adjacent_tiles: BitSet,
-    pub(crate) mine: bool,
+    /// The number of mines on the tile, at most one unless tiles can hold several.
+    pub(crate) mines: u8,
     pub(crate) exposed: bool,
-    pub(crate) flagged: bool,
-    pub(crate) adjacent_mines: u8,
+    pub(crate) mark: Mark,
 }
 
-#[derive(Debug, Copy, Clone, PartialEq)]
-enum Increment {
-    One,
-    NegOne,
-    Zero,
+impl Tile {
+    pub(crate) fn mined(&self) -> bool {
+        self.mines > 0
+    }
+
+    pub(crate) fn flagged(&self) -> bool {
+        self.flags() > 0
+    }
+
+    /// The number of flags stacked on the tile.
+    pub(crate) fn flags(&self) -> u8 {
+        match self.mark {
+            Mark::Flag(flags) => flags,
+            Mark::None | Mark::Question => 0,
+        }
+    }
+
+    /// Whether the tile carries any mark, which protects it from being exposed.
+    pub(crate) fn marked(&self) -> bool {
+        self.mark != Mark::None
+    }
 }
 
-impl Increment {
-    fn offset(&self, value: usize) -> usize {
-        match *self {
-            Self::One => value + 1,
-            Self::NegOne => value.saturating_sub(1),
-            Self::Zero => value,
+/// A tile as stored by a [`Board`], packed into 32 bits: the number of mines in the lowest byte,
+/// the number of flags in the next, then a bit each for a question mark, being exposed and being
+/// missing.
+#[derive(Debug, Default, Clone, Copy)]
+struct Packed(u32);
+
+impl Packed {
+    const MINES: u32 = 0xff;
+    const FLAGS_SHIFT: u32 = 8;
+    const FLAGS: u32 = 0xff << Self::FLAGS_SHIFT;
+    const QUESTION: u32 = 1 << 16;
+    const EXPOSED: u32 = 1 << 17;
+    const MISSING: u32 = 1 << 18;
+
+    fn mines(self) -> u8 {
+        (self.0 & Self::MINES) as u8
+    }
+
+    fn set_mines(&mut self, mines: u8) {
+        self.0 = self.0 & !Self::MINES | u32::from(mines);
+    }
+
+    fn mined(self) -> bool {
+        self.mines() > 0
+    }
+
+    fn flags(self) -> u8 {
+        ((self.0 & Self::FLAGS) >> Self::FLAGS_SHIFT) as u8
+    }
+
+    fn mark(self) -> Mark {
+        match self.flags() {
+            0 if self.0 & Self::QUESTION != 0 => Mark::Question,
+            0 => Mark::None,
+            flags => Mark::Flag(flags),
         }
     }
+
+    fn set_mark(&mut self, mark: Mark) {
+        self.0 &= !(Self::FLAGS | Self::QUESTION);
+        self.0 |= match mark {
+            Mark::None => 0,
+            Mark::Flag(flags) => u32::from(flags) << Self::FLAGS_SHIFT,
+            Mark::Question => Self::QUESTION,
+        };
+    }
+
+    fn marked(self) -> bool {
+        self.0 & (Self::FLAGS | Self::QUESTION) != 0
+    }
+
+    /// Whether the flags on the tile match the mines on it exactly.
+    fn correctly_flagged(self) -> bool {
+        self.mined() && self.flags() == self.mines()
+    }
+
+    fn exposed(self) -> bool {
+        self.0 & Self::EXPOSED != 0
+    }
+
+    fn set_exposed(&mut self, exposed: bool) {
+        self.0 = self.0 & !Self::EXPOSED | if exposed { Self::EXPOSED } else { 0 };
+    }
+
+    /// Whether the tile is a gap in an irregular board, which nothing can reach.
+    fn missing(self) -> bool {
+        self.0 & Self::MISSING != 0
+    }
+
+    fn set_missing(&mut self, missing: bool) {
+        self.0 = self.0 & !Self::MISSING | if missing { Self::MISSING } else { 0 };
+    }
 }
 
-fn adjacent((row, column): Coordinate, rows: usize, columns: usize) -> impl Iterator<Item = usize> {
-    const INCREMENTS: [Increment; 3] = [Increment::One, Increment::NegOne, Increment::Zero];
+/// How far any neighbourhood reaches from a tile within its layer.
+const REACH: usize = 2;
+
+/// Whether `(row, column)` is far enough from the edges of its layer that no adjacent tile is
+/// across an edge.
+fn is_interior((row, column): (usize, usize), rows: usize, columns: usize) -> bool {
+    row >= REACH && row + REACH < rows && column >= REACH && column + REACH < columns
+}
 
-    INCREMENTS
-        .iter()
-        .copied()
-        .flat_map(|row_incr| std::iter::repeat(row_incr).zip(INCREMENTS))
-        .filter_map(move |(row_incr, column_incr)| {
-            let row_offset = row_incr.offset(row);
-            let column_offset = column_incr.offset(column);
+/// The layer and index offsets from an interior tile to each tile adjacent to it, for tiles in
+/// even and odd rows.
+///
+/// Every interior tile in rows of the same parity has the same pattern of neighbours, so these
+/// let the neighbours of most tiles be found without going through [`Topology::adjacent`].
+fn interior_offsets(topology: Topology, rows: usize, columns: usize) -> [Vec<(isize, isize)>; 2] {
+    let index = |coord| index_from_coord(coord, rows, columns) as isize;
+    [REACH, REACH + 1].map(|row| {
+        if !is_interior((row, REACH), rows, columns) {
+            return Vec::new();
+        }
+        // the middle of three layers has neighbours in the layers on either side
+        let probe = (1, row, REACH);
+        topology
+            .adjacent(probe, 3, rows, columns)
+            .map(|coord| (coord.0 as isize - 1, index(coord) - index(probe)))
+            .collect()
+    })
+}
 
-            match (row_incr, column_incr) {
-                (Increment::Zero, Increment::Zero) => None,
-                (_, _) if row_offset < rows && column_offset < columns => {
-                    Some(index_from_coord((row_offset, column_offset), columns))
-                }
-                _ => None,
-            }
-        })
+/// The indices of the tiles adjacent to a tile, possibly including missing ones.
+enum AdjacentIndices<'a> {
+    /// A tile away from the edges of its layer, whose neighbours follow a fixed pattern.
+    Interior {
+        index: usize,
+        layer: usize,
+        layers: usize,
+        offsets: std::slice::Iter<'a, (isize, isize)>,
+    },
+    /// A tile near an edge, whose neighbours depend on how the edges wrap.
+    Edge(std::vec::IntoIter<usize>),
+}
+
+impl Iterator for AdjacentIndices<'_> {
+    type Item = usize;
+
+    fn next(&mut self) -> Option<usize> {
+        match self {
+            Self::Interior {
+                index,
+                layer,
+                layers,
+                offsets,
+            } => offsets.find_map(|&(layer_offset, offset)| {
+                layer
+                    .checked_add_signed(layer_offset)
+                    .filter(|layer| layer < layers)?;
+                index.checked_add_signed(offset)
+            }),
+            Self::Edge(indices) => indices.next(),
+        }
+    }
+}
+
+/// The tiles around the first exposed tile that are guaranteed to be free of mines.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub(crate) enum SafeZone {
+    /// Only the exposed tile itself.
+    #[default]
+    Tile,
+    /// The exposed tile and every tile adjacent to it, so the first expose always opens a cascade.
+    Neighbourhood,
+}
+
+/// The progress of a game.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum GameState {
+    /// No tile has been exposed yet.
+    NotStarted,
+    Playing,
+    Won,
+    /// A mine was exposed at the coordinate.
+    Lost(Coordinate),
+}
+
+impl GameState {
+    pub(crate) fn is_over(self) -> bool {
+        matches!(self, Self::Won | Self::Lost(_))
+    }
+}
+
+/// How mine layouts are chosen once the first tile is exposed.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub(crate) enum Generator {
+    /// Any uniformly random layout.
+    #[default]
+    Random,
+    /// A random layout that can be cleared from the first exposed tile by deduction alone,
+    /// giving up after `attempts` rejected layouts.
+    NoGuess { attempts: usize },
+}
+
+#[derive(Debug, Clone, typed_builder::TypedBuilder)]
+pub(crate) struct Settings {
+    /// The number of layers stacked on top of each other, one for a flat board.
+    #[builder(default = 1)]
+    pub(crate) layers: usize,
+    pub(crate) rows: usize,
+    pub(crate) columns: usize,
+    pub(crate) mines: usize,
+    /// The most mines a single tile can hold.
+    #[builder(default = 1)]
+    pub(crate) mines_per_tile: u8,
+    #[builder(default)]
+    pub(crate) safe_zone: SafeZone,
+    #[builder(default)]
+    pub(crate) generator: Generator,
+    #[builder(default)]
+    pub(crate) topology: Topology,
+    /// Which tiles of each layer exist, all of them if not given.
+    #[builder(default)]
+    pub(crate) mask: Option<Mask>,
+    /// Seeds mine placement: the same seed, dimensions, mine count and first exposed tile always
+    /// produce the same board.
+    pub(crate) seed: u64,
+}
+
+impl Settings {
+    /// The most rows or columns a board can have, as many as the largest board the bench
+    /// subcommand times by default.
+    pub(crate) const MAX_SIDE: usize = 10_000;
+
+    /// Check that a board of `rows` by `columns` tiles isn't too big to build.
+    pub(crate) fn check_size(rows: usize, columns: usize) -> Result<(), Error> {
+        if rows > Self::MAX_SIDE || columns > Self::MAX_SIDE {
+            return Err(Error::BoardTooBig { rows, columns });
+        }
+        Ok(())
+    }
+
+    /// The most mines `tiles` tiles can hold, leaving room for the first exposed tile, which
+    /// never holds a mine.
+    pub(crate) fn max_mines(tiles: usize, mines_per_tile: u8) -> usize {
+        tiles.saturating_sub(1) * usize::from(mines_per_tile)
+    }
 }
 
 pub(crate) struct Board {
-    tiles: Vec<Tile>,
+    tiles: Vec<Packed>,
+    // number of layers on the board
+    pub(crate) layers: usize,
     // number of rows on the board
     pub(crate) rows: usize,
     // number of columns on the board
     pub(crate) columns: usize,
     // the total number of mines
     mines: usize,
-    flagged_cells: usize,
-    // the total number of correctly flagged mines, allows checking a win in O(1)
-    correctly_flagged_mines: usize,
-    // the exposed tiles
-    seen: BitSet<usize>,
+    mines_per_tile: u8,
+    // the number of tiles holding at least one mine
+    mined_tiles: usize,
+    // the number of tiles missing from an irregular board
+    gaps: usize,
+    safe_zone: SafeZone,
+    generator: Generator,
+    topology: Topology,
+    // the neighbours of interior tiles in even and odd rows, see `interior_offsets`
+    offsets: [Vec<(isize, isize)>; 2],
+    // boards loaded from a layout have no seed
+    seed: Option<u64>,
+    rng: ChaCha8Rng,
+    // mines are placed on the first expose, so that it can never lose the game
+    placed: bool,
+    state: GameState,
+    // the total number of flags placed, counting every flag in a stack
+    flags: usize,
+    // the number of tiles flagged with exactly as many flags as mines, allows checking a win in
+    // O(1)
+    correctly_flagged_tiles: usize,
+    // the number of exposed tiles
+    exposed: usize,
+    // the changes made so far by the action in progress
+    pending: Changes,
+    // actions that can be undone, most recent last
+    history: Vec<Action>,
+    // actions that can be redone, most recently undone last
+    future: Vec<Action>,
+    undone: bool,
+    // bumped by every action, undo and redo, so views of the board know when to refresh
+    revision: usize,
+    // the last mine probabilities computed and the revision they were computed for
+    probabilities: RefCell<Option<(usize, Rc<Probabilities>)>>,
+}
+
+/// A reversible change to the mark on a single tile.
+#[derive(Debug, Clone, Copy)]
+struct MarkChange {
+    index: usize,
+    from: Mark,
+    to: Mark,
+}
+
+/// The tiles exposed by an action, listed while there are few of them and kept as a bit per tile
+/// once that takes less memory, so that a cascade across a huge board stays cheap to record.
+#[derive(Debug, Clone)]
+enum Exposures {
+    Sparse(Vec<usize>),
+    Dense(BitSet),
 }
 
-fn index_from_coord((r, c): Coordinate, columns: usize) -> usize {
-    r * columns + c
+impl Default for Exposures {
+    fn default() -> Self {
+        Self::Sparse(Vec::new())
+    }
 }
 
-fn coord_from_index(index: usize, columns: usize) -> Coordinate {
-    (index / columns, index % columns)
+impl Exposures {
+    /// Add the tile at `index` on a board of `tiles` tiles.
+    fn insert(&mut self, index: usize, tiles: usize) {
+        match self {
+            Self::Sparse(indices) => {
+                indices.push(index);
+                if indices.len() * usize::BITS as usize > tiles {
+                    let mut dense = BitSet::with_capacity(tiles);
+                    dense.extend(indices.iter().copied());
+                    *self = Self::Dense(dense);
+                }
+            }
+            Self::Dense(indices) => {
+                indices.insert(index);
+            }
+        }
+    }
+
+    fn is_empty(&self) -> bool {
+        match self {
+            Self::Sparse(indices) => indices.is_empty(),
+            Self::Dense(indices) => indices.is_empty(),
+        }
+    }
+
+    fn iter(&self) -> Box<dyn Iterator<Item = usize> + '_> {
+        match self {
+            Self::Sparse(indices) => Box::new(indices.iter().copied()),
+            Self::Dense(indices) => Box::new(indices.iter()),
+        }
+    }
+}
+
+/// Everything changed by one call to a mutating method of [`Board`], such as an expose and its
+/// whole cascade.
+#[derive(Debug, Clone, Default)]
+struct Changes {
+    exposures: Exposures,
+    marks: Vec<MarkChange>,
+}
+
+impl Changes {
+    fn is_empty(&self) -> bool {
+        self.exposures.is_empty() && self.marks.is_empty()
+    }
+}
+
+/// The changes made by an action and the state of the game either side of it.
+#[derive(Debug, Clone)]
+struct Action {
+    changes: Changes,
+    before: GameState,
+    after: GameState,
+    /// The index of the tile each mine went on, if the action placed them.
+    placed: Option<Vec<usize>>,
+}
+
+fn index_from_coord((l, r, c): Coordinate, rows: usize, columns: usize) -> usize {
+    (l * rows + r) * columns + c
+}
+
+fn coord_from_index(index: usize, rows: usize, columns: usize) -> Coordinate {
+    let (layer, tile) = (index / (rows * columns), index % (rows * columns));
+    (layer, tile / columns, tile % columns)
+}
+
+/// A single tile of a text layout.
+#[derive(Default)]
+struct LayoutTile {
+    mines: u8,
+    exposed: bool,
+    flags: u8,
+    question: bool,
+    missing: bool,
+}
+
+impl LayoutTile {
+    fn from_char(character: char) -> Option<Self> {
+        let (mines, exposed, flags, missing) = match character {
+            '.' => (0, false, 0, false),
+            '*' => (1, false, 0, false),
+            '2'..='9' => (character.to_digit(10)? as u8, false, 0, false),
+            'o' => (0, true, 0, false),
+            'f' => (0, false, 1, false),
+            'F' => (1, false, 1, false),
+            '#' => (0, false, 0, true),
+            _ => return None,
+        };
+        Some(Self {
+            mines,
+            exposed,
+            flags,
+            missing,
+            ..Self::default()
+        })
+    }
+
+    /// Apply a `!` or `?` following the tile, returning whether it can carry that mark.
+    fn add_mark(&mut self, character: char) -> bool {
+        if self.exposed || self.missing || self.question {
+            return false;
+        }
+        match character {
+            '!' => match self.flags.checked_add(1) {
+                Some(flags) => self.flags = flags,
+                None => return false,
+            },
+            '?' if self.flags == 0 => self.question = true,
+            _ => return false,
+        }
+        true
+    }
+
+    /// Write the tile in as few characters as possible, or describe why it can't be written.
+    fn write(&self, layout: &mut String) -> Result<(), &'static str> {
+        if self.missing {
+            layout.push('#');
+            return Ok(());
+        }
+        if self.exposed {
+            if self.mines > 0 {
+                return Err("it's an exposed mine");
+            }
+            layout.push('o');
+            return Ok(());
+        }
+
+        match (self.mines, self.flags) {
+            (0, 1) => layout.push('f'),
+            (1, 1) => layout.push('F'),
+            (mines, flags) => {
+                layout.push(match mines {
+                    0 => '.',
+                    1 => '*',
+                    mines => char::from_digit(mines.into(), 10).ok_or("it holds over 9 mines")?,
+                });
+                layout.extend(std::iter::repeat_n('!', flags.into()));
+            }
+        }
+        if self.question {
+            layout.push('?');
+        }
+        Ok(())
+    }
+}
+
+/// Read one row of a text layout from line `line`.
+fn parse_layout_row(text: &str, line: usize) -> Result<Vec<LayoutTile>, Error> {
+    let error = |column: usize, character| Error::ParseLayout {
+        line,
+        column: column + 1,
+        character,
+    };
+
+    let mut row = Vec::new();
+    let mut characters = text.chars().enumerate().peekable();
+    while let Some((column, character)) = characters.next() {
+        let mut tile = LayoutTile::from_char(character).ok_or(error(column, character))?;
+        while let Some((column, character)) =
+            characters.next_if(|&(_, character)| matches!(character, '!' | '?'))
+        {
+            if !tile.add_mark(character) {
+                return Err(error(column, character));
+            }
+        }
+        row.push(tile);
+    }
+    Ok(row)
+}
+
+/// A uniformly distributed integer in `0..n`, using rejection sampling to avoid modulo bias.
+fn below(rng: &mut impl RngCore, n: usize) -> usize {
+    let n = n as u64;
+    // the largest multiple of `n` that fits in a u64
+    let zone = u64::MAX - (u64::MAX - n + 1) % n;
+    loop {
+        let value = rng.next_u64();
+        if value <= zone {
+            return (value % n) as usize;
+        }
+    }
 }
 
 impl Board {
-    pub(crate) fn new(rows: usize, columns: usize, mines: usize) -> Result<Self, Error> {
-        let mut rng = rand::thread_rng();
-        let samples = rand::seq::index::sample(&mut rng, rows * columns, mines)
-            .into_iter()
-            .collect::<BitSet>();
+    pub(crate) fn new(
+        Settings {
+            layers,
+            rows,
+            columns,
+            mines,
+            mines_per_tile,
+            safe_zone,
+            generator,
+            topology,
+            mask,
+            seed,
+        }: Settings,
+    ) -> Result<Self, Error> {
+        Settings::check_size(rows, columns)?;
+        topology.validate(rows)?;
+        if let Some(mask) = &mask {
+            if (mask.rows(), mask.columns()) != (rows, columns) {
+                return Err(Error::MaskSize {
+                    mask_rows: mask.rows(),
+                    mask_columns: mask.columns(),
+                    rows,
+                    columns,
+                });
+            }
+            if mask.tiles() == 0 {
+                return Err(Error::EmptyMask);
+            }
+        }
+        let ntiles = layers * mask.as_ref().map_or(rows * columns, Mask::tiles);
+        let max_mines = Settings::max_mines(ntiles, mines_per_tile);
+        if mines > max_mines {
+            return Err(Error::TooManyMines {
+                mines,
+                tiles: ntiles,
+                max_mines,
+            });
+        }
 
-        let tiles = (0..rows)
-            .flat_map(|row| std::iter::repeat(row).zip(0..columns))
-            .enumerate()
-            .map(|(i, point)| {
-                // compute the tiles adjacent to the one being constructed
-                let adjacent_tiles = adjacent(point, rows, columns).collect::<BitSet>();
-
-                // sum the number of adjacent tiles that are in the randomly generated mines set
-                let adjacent_mines = adjacent_tiles
-                    .iter()
-                    .fold(0, |total, index| total + u8::from(samples.contains(index)));
-                assert!(adjacent_mines <= 8);
-
-                Tile {
-                    adjacent_tiles,
-                    mine: samples.contains(i),
-                    exposed: false,
-                    flagged: false,
-                    adjacent_mines,
+        let mut tiles = vec![Packed::default(); layers * rows * columns];
+        let mut gaps = 0;
+        if let Some(mask) = &mask {
+            for (index, tile) in tiles.iter_mut().enumerate() {
+                let (_, row, column) = coord_from_index(index, rows, columns);
+                if !mask.contains((row, column)) {
+                    tile.set_missing(true);
+                    gaps += 1;
                 }
-            })
-            .collect::<Vec<_>>();
+            }
+        }
 
         Ok(Self {
+            layers,
             rows,
             columns,
             tiles,
             mines,
-            flagged_cells: Default::default(),
-            correctly_flagged_mines: Default::default(),
-            seen: Default::default(),
+            mines_per_tile,
+            mined_tiles: Default::default(),
+            gaps,
+            safe_zone,
+            generator,
+            topology,
+            offsets: interior_offsets(topology, rows, columns),
+            seed: Some(seed),
+            rng: ChaCha8Rng::seed_from_u64(seed),
+            placed: false,
+            state: GameState::NotStarted,
+            flags: Default::default(),
+            correctly_flagged_tiles: Default::default(),
+            exposed: Default::default(),
+            pending: Default::default(),
+            history: Default::default(),
+            future: Default::default(),
+            undone: false,
+            revision: 0,
+            probabilities: RefCell::default(),
         })
     }
 
+    /// Build a board from a text layout, one line per row:
+    ///
+    /// - `.` a hidden tile
+    /// - `*` a hidden mine
+    /// - `2` to `9` a hidden tile holding that many mines
+    /// - `o` an exposed tile
+    /// - `f` a flagged tile without a mine, the same as `.!`
+    /// - `F` a flagged mine, the same as `*!`
+    /// - `#` a gap where there is no tile
+    ///
+    /// A hidden tile may be followed by a `!` for each flag on it or by a `?` for a question
+    /// mark, so `3!!` is a tile holding three mines with two flags on it.
+    ///
+    /// Blank lines separate the layers of a layered board, and every layer must have the same
+    /// number of rows. Trailing whitespace and leading or trailing blank lines are ignored.
+    pub(crate) fn from_layout(layout: &str, topology: Topology) -> Result<Self, Error> {
+        let mut grid = Vec::<Vec<Vec<LayoutTile>>>::new();
+        let mut layer_ended = true;
+
+        for (i, line) in layout.lines().enumerate() {
+            let line = line.trim_end();
+            if line.is_empty() {
+                if !layer_ended {
+                    if let [first, .., last] = grid.as_slice() {
+                        if first.len() != last.len() {
+                            return Err(Error::UnevenLayers {
+                                line: i + 1,
+                                expected: first.len(),
+                                found: last.len(),
+                            });
+                        }
+                    }
+                }
+                layer_ended = true;
+                continue;
+            }
+
+            let row = parse_layout_row(line, i + 1)?;
+
+            if let Some(first) = grid.first().and_then(|layer| layer.first()) {
+                if first.len() != row.len() {
+                    return Err(Error::RaggedLayout {
+                        line: i + 1,
+                        expected: first.len(),
+                        found: row.len(),
+                    });
+                }
+            }
+            if layer_ended {
+                grid.push(Vec::new());
+                layer_ended = false;
+            }
+            if let Some(layer) = grid.last_mut() {
+                layer.push(row);
+            }
+        }
+
+        let layers = grid.len();
+        let rows = grid.first().map_or(0, Vec::len);
+        let columns = grid
+            .first()
+            .and_then(|layer| layer.first())
+            .map_or(0, Vec::len);
+        if rows == 0 || columns == 0 {
+            return Err(Error::EmptyLayout);
+        }
+        if let Some(last) = grid.last() {
+            if last.len() != rows {
+                return Err(Error::UnevenLayers {
+                    line: layout.lines().count(),
+                    expected: rows,
+                    found: last.len(),
+                });
+            }
+        }
+
+        let layout = grid.into_iter().flatten().flatten().collect::<Vec<_>>();
+
+        // a layout may leave no tile free of mines, so they're counted once the board exists
+        let mut board = Self::new(
+            Settings::builder()
+                .layers(layers)
+                .rows(rows)
+                .columns(columns)
+                .mines(0)
+                .mines_per_tile(
+                    layout
+                        .iter()
+                        .map(|tile| tile.mines.max(tile.flags))
+                        .max()
+                        .unwrap_or_default()
+                        .max(1),
+                )
+                .topology(topology)
+                .seed(Default::default())
+                .build(),
+        )?;
+        board.seed = None;
+        board.mines = layout.iter().map(|tile| usize::from(tile.mines)).sum();
+        for (tile, layout_tile) in board.tiles.iter_mut().zip(&layout) {
+            tile.set_missing(layout_tile.missing);
+        }
+        board.gaps = layout.iter().filter(|tile| tile.missing).count();
+        if board.gaps == board.tiles.len() {
+            return Err(Error::EmptyLayout);
+        }
+        board.install_mines(
+            layout
+                .iter()
+                .enumerate()
+                .flat_map(|(index, tile)| std::iter::repeat_n(index, tile.mines.into())),
+        );
+        board.state = GameState::Playing;
+
+        let flags = layout.iter().map(|tile| usize::from(tile.flags)).sum();
+        if flags > board.mines {
+            return Err(Error::TooManyFlags {
+                flags,
+                mines: board.mines,
+            });
+        }
+        for (i, tile) in layout.into_iter().enumerate() {
+            if tile.flags > 0 {
+                board.set_mark(i, Mark::Flag(tile.flags));
+            } else if tile.question {
+                board.set_mark(i, Mark::Question);
+            } else if tile.exposed {
+                board.expose_tile(i);
+            }
+        }
+        board.update_won();
+        // the layout is where the game starts, not something to undo
+        board.pending = Changes::default();
+
+        Ok(board)
+    }
+
+    /// Write the board in the format read by [`Board::from_layout`], which can't describe an
+    /// exposed mine or a tile holding more than 9 mines.
+    pub(crate) fn to_layout(&self) -> Result<String, Error> {
+        let mut layout = String::new();
+        for (index, tile) in self.tiles.iter().enumerate() {
+            let coord @ (_, row, column) = coord_from_index(index, self.rows, self.columns);
+            if index > 0 && (row, column) == (0, 0) {
+                layout.push('\n');
+            }
+            LayoutTile {
+                mines: tile.mines(),
+                exposed: tile.exposed(),
+                flags: tile.flags(),
+                question: tile.mark() == Mark::Question,
+                missing: tile.missing(),
+            }
+            .write(&mut layout)
+            .map_err(|reason| Error::UnrepresentableTile { coord, reason })?;
+            if column + 1 == self.columns {
+                layout.push('\n');
+            }
+        }
+        Ok(layout)
+    }
+
+    /// Place mines according to the board's generator, keeping the safe zone around `coord` clear.
+    fn place_mines(&mut self, coord: Coordinate) -> Result<(), Error> {
+        match self.generator {
+            Generator::Random => {
+                let mines = self.sample_mines(coord);
+                self.install_mines(mines);
+                Ok(())
+            }
+            Generator::NoGuess { attempts } => {
+                for _ in 0..attempts {
+                    let mines = self.sample_mines(coord);
+                    self.install_mines(mines);
+                    if solver::solvable(self, coord)? {
+                        return Ok(());
+                    }
+                }
+                // leave the board as it was, so another expose can try again
+                self.install_mines(std::iter::empty());
+                self.placed = false;
+                Err(Error::GenerateNoGuessBoard {
+                    attempts,
+                    mines: self.mines,
+                    tiles: self.tiles.len() - self.gaps,
+                })
+            }
+        }
+    }
+
+    /// Sample the index of the tile each mine goes on, keeping the safe zone around `coord` clear.
+    fn sample_mines(&mut self, coord: Coordinate) -> Vec<usize> {
+        let ntiles = self.tiles.len() - self.gaps;
+        let index = self.index_from_coord(coord);
+
+        let mut excluded = match self.safe_zone {
+            SafeZone::Tile => Vec::new(),
+            SafeZone::Neighbourhood => self.adjacent_indices(index).collect(),
+        };
+        // protect only the exposed tile if its neighbourhood leaves too little room for the mines
+        let capacity = usize::from(self.mines_per_tile);
+        if ntiles.saturating_sub(excluded.len() + 1) * capacity < self.mines {
+            excluded.clear();
+        }
+        excluded.push(index);
+
+        // the tiles that can't hold mines, sorted, each paired with the number of tiles before it
+        // that can
+        let mut skipped = self
+            .tiles
+            .iter()
+            .enumerate()
+            .filter(|(_, tile)| tile.missing())
+            .map(|(index, _)| index)
+            .chain(excluded)
+            .collect::<Vec<_>>();
+        skipped.sort_unstable();
+        skipped.dedup();
+        let allowed_before = skipped
+            .iter()
+            .enumerate()
+            .map(|(i, &index)| index - i)
+            .collect::<Vec<_>>();
+
+        // each tile that can hold mines offers one slot per mine it can hold, in order of index
+        let slots = (self.tiles.len() - skipped.len()) * capacity;
+        let tile_of_slot = |slot: usize| {
+            let allowed = slot / capacity;
+            allowed + allowed_before.partition_point(|&before| before <= allowed)
+        };
+        let nmines = self.mines.min(slots);
+
+        // a partial Fisher-Yates shuffle of the slots, written out rather than using `rand::seq`
+        // so that the layout for a given seed doesn't change when `rand`'s sampling algorithms do
+        //
+        // only the slots moved out of place are stored, so the memory used grows with the number
+        // of mines rather than the size of the board
+        let mut moved = HashMap::<usize, usize>::new();
+        let mut mines = Vec::with_capacity(nmines);
+        for i in 0..nmines {
+            let j = i + below(&mut self.rng, slots - i);
+            let at_i = moved.remove(&i).unwrap_or(i);
+            let at_j = if j == i {
+                at_i
+            } else {
+                moved.insert(j, at_i).unwrap_or(j)
+            };
+            mines.push(tile_of_slot(at_j));
+        }
+        mines
+    }
+
+    /// Put a mine on the tile at each index in `mines`, which repeats the indices of tiles
+    /// holding several, replacing any mines already placed.
+    fn install_mines(&mut self, mines: impl IntoIterator<Item = usize>) {
+        for tile in &mut self.tiles {
+            tile.set_mines(0);
+        }
+        for index in mines {
+            let tile = &mut self.tiles[index];
+            tile.set_mines(tile.mines() + 1);
+        }
+
+        self.mined_tiles = self.tiles.iter().filter(|tile| tile.mined()).count();
+        // flags may have been placed before there were any mines to be correct about
+        self.correctly_flagged_tiles = self
+            .tiles
+            .iter()
+            .filter(|tile| tile.correctly_flagged())
+            .count();
+        self.placed = true;
+    }
+
+    /// The index of the tile each mine is on, repeated for tiles holding several.
+    fn mine_indices(&self) -> Vec<usize> {
+        self.tiles
+            .iter()
+            .enumerate()
+            .flat_map(|(index, tile)| std::iter::repeat_n(index, tile.mines().into()))
+            .collect()
+    }
+
+    /// The seed used to place mines, if they were placed randomly.
+    pub(crate) fn seed(&self) -> Option<u64> {
+        self.seed
+    }
+
+    /// Whether mines have been placed, which happens on the first expose for random boards.
+    pub(crate) fn started(&self) -> bool {
+        self.placed
+    }
+
+    pub(crate) fn state(&self) -> GameState {
+        self.state
+    }
+
+    fn check_playing(&self) -> Result<(), Error> {
+        if self.state.is_over() {
+            Err(Error::GameOver(self.state))
+        } else {
+            Ok(())
+        }
+    }
+
+    /// Finish the game once every safe tile has been exposed.
+    fn update_won(&mut self) {
+        if self.state == GameState::Playing && self.won() {
+            self.state = GameState::Won;
+            self.flag_all();
+        }
+    }
+
+    /// Finish the game after exposing the mine at `coord`.
+    fn lose(&mut self, coord: Coordinate) {
+        self.state = GameState::Lost(coord);
+        self.expose_all();
+    }
+
+    /// The total number of mines on the board.
+    pub(crate) fn mines(&self) -> usize {
+        self.mines
+    }
+
+    /// The most mines a single tile can hold.
+    pub(crate) fn mines_per_tile(&self) -> u8 {
+        self.mines_per_tile
+    }
+
+    /// The chance of each hidden, unflagged tile holding a mine given only what the player can
+    /// see, as described by [`probability::probabilities`].
+    ///
+    /// The result is kept until the board changes, so asking again is cheap.
+    pub(crate) fn mine_probabilities(&self) -> Result<Rc<Probabilities>, Error> {
+        if let Some((revision, probabilities)) = &*self.probabilities.borrow() {
+            if *revision == self.revision {
+                return Ok(Rc::clone(probabilities));
+            }
+        }
+        let probabilities = Rc::new(probability::probabilities(self)?);
+        *self.probabilities.borrow_mut() = Some((self.revision, Rc::clone(&probabilities)));
+        Ok(probabilities)
+    }
+
+    pub(crate) fn topology(&self) -> Topology {
+        self.topology
+    }
+
+    /// The coordinates of the tiles adjacent to `coord`.
+    pub(crate) fn adjacent(&self, coord: Coordinate) -> impl Iterator<Item = Coordinate> + '_ {
+        let (rows, columns) = (self.rows, self.columns);
+        self.adjacent_indices(self.index_from_coord(coord))
+            .map(move |index| coord_from_index(index, rows, columns))
+    }
+
+    /// The indices of the tiles adjacent to the tile at `index`.
+    fn adjacent_indices(&self, index: usize) -> impl Iterator<Item = usize> + '_ {
+        let (rows, columns) = (self.rows, self.columns);
+        let (layer, row, column) = coord_from_index(index, rows, columns);
+
+        let indices = if is_interior((row, column), rows, columns) {
+            AdjacentIndices::Interior {
+                index,
+                layer,
+                layers: self.layers,
+                offsets: self.offsets[row % 2].iter(),
+            }
+        } else {
+            AdjacentIndices::Edge(
+                self.topology
+                    .adjacent((layer, row, column), self.layers, rows, columns)
+                    .map(|coord| index_from_coord(coord, rows, columns))
+                    .collect::<Vec<_>>()
+                    .into_iter(),
+            )
+        };
+        indices.filter(|&index| !self.tiles[index].missing())
+    }
+
+    /// The total number of mines on the tiles adjacent to `coord`, the number shown once it's
+    /// exposed.
+    ///
+    /// Only the mines themselves are stored, so this counts them each time.
+    pub(crate) fn adjacent_mines(&self, coord: Coordinate) -> Result<u16, Error> {
+        if !self.contains(coord) {
+            return Err(Error::GetTile(coord));
+        }
+        Ok(self.adjacent_mines_at(self.index_from_coord(coord)))
+    }
+
+    fn adjacent_mines_at(&self, index: usize) -> u16 {
+        self.adjacent_indices(index)
+            .map(|index| u16::from(self.tiles[index].mines()))
+            .sum()
+    }
+
+    /// The coordinates of every tile on the board, layer by layer and row by row.
+    pub(crate) fn coordinates(&self) -> impl Iterator<Item = Coordinate> + '_ {
+        let (rows, columns) = (self.rows, self.columns);
+        (0..self.tiles.len())
+            .filter(|&index| !self.tiles[index].missing())
+            .map(move |index| coord_from_index(index, rows, columns))
+    }
+
+    /// Whether there is a tile at `coord`, which may be missing from an irregular board.
+    pub(crate) fn contains(&self, (l, r, c): Coordinate) -> bool {
+        l < self.layers
+            && r < self.rows
+            && c < self.columns
+            && !self.tiles[self.index_from_coord((l, r, c))].missing()
+    }
+
     pub(crate) fn available_flags(&self) -> usize {
-        assert!(self.flagged_cells <= self.mines);
-        self.mines - self.flagged_cells
+        assert!(self.flags <= self.mines);
+        self.mines - self.flags
     }
 
+    /// Whether every tile is either exposed or correctly flagged, or every tile left hidden is a
+    /// mine, in constant time.
     pub(crate) fn won(&self) -> bool {
-        let nseen = self.seen.len();
-        let exposed_or_correctly_flagged = nseen + self.correctly_flagged_mines;
-        let ntiles = self.rows * self.columns;
+        let exposed_or_correctly_flagged = self.exposed + self.correctly_flagged_tiles;
+        let ntiles = self.tiles.len() - self.gaps;
 
         assert!(exposed_or_correctly_flagged <= ntiles);
 
-        ntiles == exposed_or_correctly_flagged || (self.tiles.len() - nseen) == self.mines
+        ntiles == exposed_or_correctly_flagged || (ntiles - self.exposed) == self.mined_tiles
+    }
+
+    fn index_from_coord(&self, coord: Coordinate) -> usize {
+        index_from_coord(coord, self.rows, self.columns)
+    }
+
+    /// Run a mutating operation on a board whose game isn't over, recording everything it changes
+    /// as a single action that can be undone.
+    fn record<T>(
+        &mut self,
+        operation: impl FnOnce(&mut Self) -> Result<T, Error>,
+    ) -> Result<T, Error> {
+        self.check_playing()?;
+
+        let before = self.state;
+        let placed_before = self.placed;
+        let result = operation(self);
+        let changes = std::mem::take(&mut self.pending);
+
+        if !changes.is_empty() || self.state != before {
+            let placed = (self.placed && !placed_before).then(|| self.mine_indices());
+            self.history.push(Action {
+                changes,
+                before,
+                after: self.state,
+                placed,
+            });
+            self.future.clear();
+            self.revision += 1;
+        }
+        result
     }
 
-    fn index_from_coord(&self, (r, c): Coordinate) -> usize {
-        index_from_coord((r, c), self.columns)
+    /// Undo the most recent action, returning whether there was one to undo.
+    pub(crate) fn undo(&mut self) -> bool {
+        let Some(action) = self.history.pop() else {
+            return false;
+        };
+
+        for change in action.changes.marks.iter().rev() {
+            self.apply_mark(change.index, change.from);
+        }
+        for index in action.changes.exposures.iter() {
+            self.apply_expose(index, false);
+        }
+        // the first expose is safe wherever it is, so undoing it takes the mines away again
+        if action.placed.is_some() {
+            self.install_mines(std::iter::empty());
+            self.placed = false;
+        }
+        self.state = action.before;
+        self.future.push(action);
+        self.undone = true;
+        self.revision += 1;
+        true
     }
 
-    pub(crate) fn expose(&mut self, (r, c): Coordinate) -> Result<bool, Error> {
-        if self.tile(r, c)?.mine {
-            self.tile_mut(r, c)?.exposed = true;
+    /// Redo the most recently undone action, returning whether there was one to redo.
+    pub(crate) fn redo(&mut self) -> bool {
+        let Some(action) = self.future.pop() else {
+            return false;
+        };
+
+        if let Some(mines) = &action.placed {
+            self.install_mines(mines.iter().copied());
+        }
+        for index in action.changes.exposures.iter() {
+            self.apply_expose(index, true);
+        }
+        for change in &action.changes.marks {
+            self.apply_mark(change.index, change.to);
+        }
+        self.state = action.after;
+        self.history.push(action);
+        self.revision += 1;
+        true
+    }
+
+    /// A number that changes whenever the board does.
+    pub(crate) fn revision(&self) -> usize {
+        self.revision
+    }
+
+    /// Whether any action was ever undone, in which case the game shouldn't count towards
+    /// statistics.
+    pub(crate) fn undo_used(&self) -> bool {
+        self.undone
+    }
+
+    pub(crate) fn expose(&mut self, coord: Coordinate) -> Result<GameState, Error> {
+        self.record(|board| {
+            if !board.placed {
+                // validate the coordinate before using it to place mines
+                board.tile(coord)?;
+                board.place_mines(coord)?;
+            }
+            board.state = GameState::Playing;
+
+            if board.reveal(coord)? {
+                board.lose(coord);
+            } else {
+                board.update_won();
+            }
+            Ok(board.state)
+        })
+    }
+
+    /// Expose a tile and cascade through its neighbours if none of them are mines, returning
+    /// whether the tile was a mine.
+    ///
+    /// Tiles are exposed as they're found, so each is visited at most once and the cascade takes
+    /// time in proportion to the number of tiles it exposes.
+    fn reveal(&mut self, coord: Coordinate) -> Result<bool, Error> {
+        let index = self.index_from_coord(coord);
+        // marked tiles stop the cascade and stay hidden until they're unmarked, mined or not
+        if self.tile(coord)?.marked() {
+            return Ok(false);
+        }
+        if self.tiles[index].mined() {
+            self.expose_tile(index);
             return Ok(true);
         }
 
-        let mut coordinates = [(r, c)].iter().copied().collect::<VecDeque<_>>();
+        if !self.expose_tile(index) {
+            return Ok(false);
+        }
 
-        let columns = self.columns;
+        let mut cascade = vec![index];
+        let mut neighbours = Vec::new();
+        while let Some(index) = cascade.pop() {
+            neighbours.clear();
+            neighbours.extend(self.adjacent_indices(index));
+            if neighbours
+                .iter()
+                .any(|&neighbour| self.tiles[neighbour].mined())
+            {
+                continue;
+            }
 
-        while let Some((r, c)) = coordinates.pop_front() {
-            if self.seen.insert(self.index_from_coord((r, c))) {
-                let tile = self.tile_mut(r, c)?;
+            for &neighbour in &neighbours {
+                if !self.tiles[neighbour].marked() && self.expose_tile(neighbour) {
+                    cascade.push(neighbour);
+                }
+            }
+        }
+
+        Ok(false)
+    }
 
-                tile.exposed = !(tile.mine || tile.flagged);
+    /// Expose every unmarked neighbour of an exposed tile whose number is satisfied by the flags
+    /// around it. A wrongly placed flag makes that expose a mine and loses the game.
+    pub(crate) fn chord(&mut self, coord: Coordinate) -> Result<GameState, Error> {
+        self.record(|board| {
+            let tile = board.tile(coord)?;
+            if !tile.exposed {
+                return Ok(board.state);
+            }
+            let adjacent_mines = usize::from(board.adjacent_mines(coord)?);
 
-                if tile.adjacent_mines == 0 {
-                    coordinates.extend(
-                        tile.adjacent_tiles
-                            .iter()
-                            .map(move |index| coord_from_index(index, columns)),
-                    );
+            let neighbours = board.adjacent(coord).collect::<Vec<_>>();
+            let mut flagged = 0;
+            for &neighbour in &neighbours {
+                flagged += usize::from(board.tile(neighbour)?.flags());
+            }
+            if flagged != adjacent_mines {
+                return Ok(board.state);
+            }
+
+            for neighbour in neighbours {
+                let tile = board.tile(neighbour)?;
+                if !(tile.marked() || tile.exposed) && board.reveal(neighbour)? {
+                    board.lose(neighbour);
+                    return Ok(board.state);
+                }
+            }
+            board.update_won();
+            Ok(board.state)
+        })
+    }
+
+    /// Flag every hidden neighbour of an exposed tile whose number can only be satisfied by all of
+    /// them being mines. Needs the number to equal the hidden neighbours times `mines_per_tile`.
+    pub(crate) fn flag_chord(&mut self, coord: Coordinate) -> Result<(), Error> {
+        self.record(|board| {
+            let tile = board.tile(coord)?;
+            if !tile.exposed {
+                return Ok(());
+            }
+            let adjacent_mines = usize::from(board.adjacent_mines(coord)?);
+
+            let mut unexposed = Vec::new();
+            for neighbour in board.adjacent(coord) {
+                if !board.tile(neighbour)?.exposed {
+                    unexposed.push(neighbour);
                 }
+            }
+            let capacity = board.mines_per_tile;
+            if unexposed.len() * usize::from(capacity) != adjacent_mines {
+                return Ok(());
+            }
+
+            for coord in unexposed {
+                let index = board.index_from_coord(coord);
+                let flags = board.tiles[index].flags();
+                if board.flags + usize::from(capacity - flags) <= board.mines {
+                    board.set_mark(index, Mark::Flag(capacity));
+                }
+            }
+            board.update_won();
+            Ok(())
+        })
+    }
+
+    fn expose_all(&mut self) {
+        for index in 0..self.tiles.len() {
+            if self.tiles[index].missing() {
+                continue;
+            }
+            self.set_mark(index, Mark::None);
+            self.expose_tile(index);
+        }
+    }
+
+    pub(crate) fn tile(&self, coord: Coordinate) -> Result<Tile, Error> {
+        if !self.contains(coord) {
+            return Err(Error::GetTile(coord));
+        }
+        let index = self.index_from_coord(coord);
+        let tile = self.tiles[index];
+        Ok(Tile {
+            mines: tile.mines(),
+            exposed: tile.exposed(),
+            mark: tile.mark(),
+        })
+    }
+
+    /// Expose the tile at `index` as part of the current action, returning whether it was hidden.
+    fn expose_tile(&mut self, index: usize) -> bool {
+        let hidden = !self.tiles[index].exposed();
+        if hidden {
+            self.apply_expose(index, true);
+            self.pending.exposures.insert(index, self.tiles.len());
+        }
+        hidden
+    }
+
+    fn apply_expose(&mut self, index: usize, exposed: bool) {
+        let tile = &mut self.tiles[index];
+        if tile.exposed() != exposed {
+            tile.set_exposed(exposed);
+            if exposed {
+                self.exposed += 1;
+            } else {
+                self.exposed -= 1;
+            }
+        }
+    }
+
+    /// Set the mark on the tile at `index` as part of the current action.
+    fn set_mark(&mut self, index: usize, mark: Mark) {
+        let from = self.tiles[index].mark();
+        if from != mark {
+            self.apply_mark(index, mark);
+            self.pending.marks.push(MarkChange {
+                index,
+                from,
+                to: mark,
+            });
+        }
+    }
+
+    /// Set the mark on the tile at `index`, keeping the flag counts in step.
+    fn apply_mark(&mut self, index: usize, mark: Mark) {
+        let tile = &mut self.tiles[index];
+        self.flags -= usize::from(tile.flags());
+        self.correctly_flagged_tiles -= usize::from(tile.correctly_flagged());
+        tile.set_mark(mark);
+        self.flags += usize::from(tile.flags());
+        self.correctly_flagged_tiles += usize::from(tile.correctly_flagged());
+    }
+
+    fn flag_all(&mut self) {
+        for index in 0..self.tiles.len() {
+            let tile = self.tiles[index];
+            let mark = if !tile.exposed() && tile.mined() {
+                Mark::Flag(tile.mines())
+            } else {
+                Mark::None
             };
+            self.set_mark(index, mark);
         }
+    }
 
-        Ok(false)
+    /// A copy of the board without any marks or undo history, for the solver to read the numbers
+    /// from without trusting the player's flags.
+    pub(crate) fn unmarked(&self) -> Self {
+        let mut board = Self {
+            tiles: self.tiles.clone(),
+            offsets: self.offsets.clone(),
+            rng: self.rng.clone(),
+            pending: Changes::default(),
+            history: Vec::new(),
+            future: Vec::new(),
+            probabilities: RefCell::default(),
+            ..*self
+        };
+        for index in 0..board.tiles.len() {
+            board.apply_mark(index, Mark::None);
+        }
+        board
     }
 
-    pub(crate) fn expose_all(&mut self) -> Result<(), Error> {
-        let columns = self.columns;
-        (0..self.tiles.len())
-            .map(move |i| coord_from_index(i, columns))
-            .try_for_each(|coord| {
-                self.expose(coord)?;
-                Ok(())
+    /// Add a flag to a hidden tile, clearing its flags instead once it has as many as a tile can
+    /// hold or there are none left, returning the number of flags on it.
+    pub(crate) fn flag(&mut self, coord: Coordinate) -> Result<u8, Error> {
+        self.tile(coord)?;
+        let index = self.index_from_coord(coord);
+        self.record(|board| {
+            let tile = board.tiles[index];
+            let flags = tile.flags();
+            if tile.exposed() {
+                return Ok(flags);
+            }
+
+            if flags < board.mines_per_tile && board.flags < board.mines {
+                board.set_mark(index, Mark::Flag(flags + 1));
+            } else if flags > 0 {
+                board.set_mark(index, Mark::None);
+            }
+            board.update_won();
+            Ok(board.tiles[index].flags())
+        })
+    }
+
+    /// Remove a flag from a tile, returning the number of flags left on it.
+    pub(crate) fn unflag(&mut self, coord: Coordinate) -> Result<u8, Error> {
+        self.tile(coord)?;
+        let index = self.index_from_coord(coord);
+        self.record(|board| {
+            let mark = match board.tiles[index].flags() {
+                0 => return Ok(0),
+                1 => Mark::None,
+                flags => Mark::Flag(flags - 1),
+            };
+            board.set_mark(index, mark);
+            board.update_won();
+            Ok(board.tiles[index].flags())
+        })
+    }
+
+    /// Cycle the mark on a hidden tile from none through each number of flags a tile can hold to
+    /// a question mark, skipping flags when there are none left.
+    pub(crate) fn mark(&mut self, coord: Coordinate) -> Result<Mark, Error> {
+        self.tile(coord)?;
+        let index = self.index_from_coord(coord);
+        self.record(|board| {
+            let tile = board.tiles[index];
+            if tile.exposed() {
+                return Ok(tile.mark());
+            }
+
+            let more_flags = board.flags < board.mines;
+            let mark = match tile.mark() {
+                Mark::None if more_flags => Mark::Flag(1),
+                Mark::Flag(flags) if more_flags && flags < board.mines_per_tile => {
+                    Mark::Flag(flags + 1)
+                }
+                Mark::None | Mark::Flag(_) => Mark::Question,
+                Mark::Question => Mark::None,
+            };
+            board.set_mark(index, mark);
+            board.update_won();
+            Ok(mark)
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::topology::{Grid, Neighbourhood, Wrap};
+
+    #[test]
+    fn new_rejects_boards_too_big_to_build() {
+        let settings = Settings::builder()
+            .rows(2)
+            .columns(Settings::MAX_SIDE + 1)
+            .mines(1)
+            .seed(0)
+            .build();
+        assert!(matches!(
+            Board::new(settings),
+            Err(Error::BoardTooBig { rows: 2, .. })
+        ));
+        assert!(matches!(
+            Board::from_layout(&".".repeat(Settings::MAX_SIDE + 1), Topology::default()),
+            Err(Error::BoardTooBig { rows: 1, .. })
+        ));
+    }
+
+    #[test]
+    fn new_rejects_more_mines_than_fit() {
+        let settings = Settings::builder()
+            .rows(2)
+            .columns(2)
+            .mines(4)
+            .seed(0)
+            .build();
+        assert!(matches!(
+            Board::new(settings),
+            Err(Error::TooManyMines {
+                mines: 4,
+                tiles: 4,
+                max_mines: 3
             })
+        ));
     }
 
-    pub(crate) fn tile(&self, i: usize, j: usize) -> Result<&Tile, Error> {
-        self.tiles
-            .get(self.index_from_coord((i, j)))
-            .ok_or(Error::GetTile((i, j)))
+    #[test]
+    fn failed_no_guess_generation_leaves_no_mines() {
+        // whichever side of the middle tile the mine is on, finding it takes a guess
+        let mut board = Board::new(
+            Settings::builder()
+                .rows(1)
+                .columns(4)
+                .mines(1)
+                .generator(Generator::NoGuess { attempts: 3 })
+                .mask(Some(Mask::parse("...#\n").unwrap()))
+                .seed(0)
+                .build(),
+        )
+        .unwrap();
+
+        assert!(matches!(
+            board.expose((0, 0, 1)),
+            Err(Error::GenerateNoGuessBoard {
+                attempts: 3,
+                mines: 1,
+                tiles: 3
+            })
+        ));
+        assert!(!board.placed);
+        assert!(board
+            .tiles
+            .iter()
+            .all(|tile| !tile.mined() && !tile.exposed()));
     }
 
-    pub(crate) fn tile_mut(&mut self, i: usize, j: usize) -> Result<&mut Tile, Error> {
-        let index = self.index_from_coord((i, j));
-        self.tiles.get_mut(index).ok_or(Error::GetTile((i, j)))
+    #[test]
+    fn the_first_expose_never_hits_a_mine() {
+        // every tile but one holds a mine, so the first exposed has to be the one without
+        for index in 0..20 {
+            let settings = Settings::builder().rows(4).columns(5).mines(19);
+            let mut board = Board::new(settings.seed(index as u64).build()).unwrap();
+            let coord = coord_from_index(index, 4, 5);
+            assert_eq!(
+                board.expose(coord).unwrap(),
+                GameState::Won,
+                "hit a mine at {coord:?}"
+            );
+        }
     }
 
-    pub(crate) fn flag_all(&mut self) {
-        for tile in self.tiles.iter_mut() {
-            tile.flagged = !tile.exposed && tile.mine;
+    #[test]
+    fn an_open_start_clears_the_neighbourhood_of_the_first_expose() {
+        for (seed, coord) in [(0, 0, 0), (0, 5, 7), (0, 0, 4), (0, 2, 3)]
+            .into_iter()
+            .enumerate()
+        {
+            let mut board = Board::new(
+                Settings::builder()
+                    .rows(6)
+                    .columns(8)
+                    .mines(30)
+                    .seed(seed as u64)
+                    .safe_zone(SafeZone::Neighbourhood)
+                    .build(),
+            )
+            .unwrap();
+            board.expose(coord).unwrap();
+            for coord in board.adjacent(coord).chain([coord]) {
+                let tile = board.tile(coord).unwrap();
+                assert!(!tile.mined() && tile.exposed, "mine next to {coord:?}");
+            }
         }
+
+        // with no room left around it, only the exposed tile itself is kept clear
+        let mut board = Board::new(
+            Settings::builder()
+                .rows(3)
+                .columns(3)
+                .mines(8)
+                .seed(0)
+                .safe_zone(SafeZone::Neighbourhood)
+                .build(),
+        )
+        .unwrap();
+        assert_eq!(board.expose((0, 0, 0)).unwrap(), GameState::Won);
+    }
+
+    #[test]
+    fn a_seed_always_lays_out_the_same_board() {
+        let settings = Settings::builder()
+            .rows(6)
+            .columns(8)
+            .mines(10)
+            .seed(2024)
+            .build();
+        let mut board = Board::new(settings.clone()).unwrap();
+        board.expose((0, 2, 3)).unwrap();
+        assert_eq!(board.seed(), Some(2024));
+        // changing this layout breaks every seed shared so far
+        assert_eq!(
+            board.to_layout().unwrap(),
+            "\
+...*..*.
+........
+*.*o..**
+........
+*......*
+..*.*...
+"
+        );
+
+        let mut other = Board::new(Settings {
+            seed: 2025,
+            ..settings
+        })
+        .unwrap();
+        other.expose((0, 2, 3)).unwrap();
+        assert_ne!(other.to_layout().unwrap(), board.to_layout().unwrap());
     }
 
-    pub(crate) fn flag(&mut self, i: usize, j: usize) -> Result<bool, Error> {
-        let nflagged = self.flagged_cells;
-        let tile = self.tile(i, j)?;
-        let was_flagged = tile.flagged;
-        let flagged = !was_flagged;
-        let nmines = self.mines;
-        self.correctly_flagged_mines += usize::from(flagged && tile.mine);
-        if was_flagged {
-            self.flagged_cells = self.flagged_cells.saturating_sub(1);
-            self.tile_mut(i, j)?.flagged = flagged;
-        } else if nflagged < nmines && !self.tile(i, j)?.exposed {
-            self.tile_mut(i, j)?.flagged = flagged;
-            self.flagged_cells += 1;
+    #[test]
+    fn layouts_round_trip() {
+        let layout = "o.*3\n.?3!!f.\n\nF*?..\n2!...\n";
+        let board = Board::from_layout(layout, Topology::default()).unwrap();
+        assert_eq!(board.mines(), 11);
+        assert_eq!(board.mines_per_tile(), 3);
+        assert_eq!(board.tile((0, 1, 1)).unwrap().mark, Mark::Flag(2));
+        assert_eq!(board.tile((1, 0, 1)).unwrap().mark, Mark::Question);
+        assert_eq!(board.to_layout().unwrap(), layout);
+    }
+
+    #[test]
+    fn played_boards_round_trip() {
+        let mut board = Board::new(
+            Settings::builder()
+                .rows(8)
+                .columns(8)
+                .mines(20)
+                .mines_per_tile(2)
+                .seed(7)
+                .build(),
+        )
+        .unwrap();
+        board.expose((0, 0, 0)).unwrap();
+        let hidden = board
+            .coordinates()
+            .filter(|&coord| !board.tile(coord).unwrap().exposed)
+            .collect::<Vec<_>>();
+        board.flag(hidden[0]).unwrap();
+        board.flag(hidden[0]).unwrap();
+        board.mark(hidden[1]).unwrap();
+        board.mark(hidden[1]).unwrap();
+        board.mark(hidden[1]).unwrap();
+
+        let layout = board.to_layout().unwrap();
+        let loaded = Board::from_layout(&layout, Topology::default()).unwrap();
+        assert_eq!(loaded.to_layout().unwrap(), layout);
+        for coord in board.coordinates() {
+            let (tile, loaded_tile) = (board.tile(coord).unwrap(), loaded.tile(coord).unwrap());
+            assert_eq!(
+                (tile.mines, tile.exposed, tile.mark),
+                (loaded_tile.mines, loaded_tile.exposed, loaded_tile.mark)
+            );
         }
-        Ok(flagged)
+    }
+
+    #[test]
+    fn lost_boards_have_no_layout() {
+        let mut board = Board::from_layout("*.\n", Topology::default()).unwrap();
+        board.expose((0, 0, 0)).unwrap();
+        assert!(matches!(
+            board.to_layout(),
+            Err(Error::UnrepresentableTile {
+                coord: (0, 0, 0),
+                ..
+            })
+        ));
+    }
+
+    #[test]
+    fn layouts_reject_more_flags_than_mines() {
+        assert!(matches!(
+            Board::from_layout("f.\n", Topology::default()),
+            Err(Error::TooManyFlags { flags: 1, mines: 0 })
+        ));
+        assert!(matches!(
+            Board::from_layout("2!!!.\n", Topology::default()),
+            Err(Error::TooManyFlags { flags: 3, mines: 2 })
+        ));
+        // a wrong flag is fine while there's a mine for it to stand for
+        assert!(Board::from_layout("f*\n", Topology::default()).is_ok());
+    }
+
+    #[test]
+    fn layouts_need_rows_of_equal_length() {
+        assert!(matches!(
+            Board::from_layout("...\n\n..\n", Topology::default()),
+            Err(Error::RaggedLayout {
+                line: 3,
+                expected: 3,
+                found: 2
+            })
+        ));
+        assert!(matches!(
+            Board::from_layout(".x\n", Topology::default()),
+            Err(Error::ParseLayout {
+                line: 1,
+                column: 2,
+                character: 'x'
+            })
+        ));
+        assert!(matches!(
+            Board::from_layout("\n\n", Topology::default()),
+            Err(Error::EmptyLayout)
+        ));
+    }
+
+    #[test]
+    fn layouts_reject_marks_on_exposed_tiles() {
+        assert!(matches!(
+            Board::from_layout("..\no?\n", Topology::default()),
+            Err(Error::ParseLayout {
+                line: 2,
+                column: 2,
+                character: '?'
+            })
+        ));
+        assert!(matches!(
+            Board::from_layout(".?!\n", Topology::default()),
+            Err(Error::ParseLayout {
+                line: 1,
+                column: 3,
+                character: '!'
+            })
+        ));
+    }
+
+    #[test]
+    fn flag_chord_flags_hidden_neighbours_matching_the_number() {
+        let mut board = Board::from_layout("*o.\n**o\n", Topology::default()).unwrap();
+        board.flag_chord((0, 0, 1)).unwrap();
+        assert!(!board.tile((0, 0, 0)).unwrap().flagged());
+
+        board.flag_chord((0, 1, 2)).unwrap();
+        assert!(!board.tile((0, 0, 2)).unwrap().flagged());
+
+        let mut board = Board::from_layout("*oo.\n*oo.\n", Topology::default()).unwrap();
+        board.flag_chord((0, 0, 1)).unwrap();
+        assert!(board.tile((0, 0, 0)).unwrap().flagged());
+        assert!(board.tile((0, 1, 0)).unwrap().flagged());
+        assert!(!board.tile((0, 0, 3)).unwrap().flagged());
+    }
+
+    #[test]
+    fn flag_chord_with_several_mines_to_a_tile_needs_them_full() {
+        // two mines between two tiles that could each hold two
+        let mut board = Board::from_layout("2o.o\n", Topology::default()).unwrap();
+        board.flag_chord((0, 0, 1)).unwrap();
+        assert_eq!(board.tile((0, 0, 0)).unwrap().mark, Mark::None);
+        assert_eq!(board.tile((0, 0, 2)).unwrap().mark, Mark::None);
+
+        let mut board = Board::from_layout("2oo.\n", Topology::default()).unwrap();
+        board.flag_chord((0, 0, 1)).unwrap();
+        assert_eq!(board.tile((0, 0, 0)).unwrap().mark, Mark::Flag(2));
+    }
+
+    #[test]
+    fn chord_exposes_the_unflagged_neighbours_of_a_satisfied_number() {
+        let mut board = Board::from_layout("Fo..\n", Topology::default()).unwrap();
+        assert_eq!(board.chord((0, 0, 1)).unwrap(), GameState::Won);
+        assert!(board.tile((0, 0, 2)).unwrap().exposed);
+    }
+
+    #[test]
+    fn chord_around_a_wrong_flag_loses() {
+        let mut board = Board::from_layout("*of\n", Topology::default()).unwrap();
+        assert_eq!(board.chord((0, 0, 1)).unwrap(), GameState::Lost((0, 0, 0)));
+    }
+
+    #[test]
+    fn exposing_a_flagged_mine_keeps_playing() {
+        let mut board = Board::from_layout("F.\n..\n", Topology::default()).unwrap();
+        assert_eq!(board.expose((0, 0, 0)).unwrap(), GameState::Playing);
+        assert!(!board.tile((0, 0, 0)).unwrap().exposed);
+    }
+
+    #[test]
+    fn exposing_a_question_marked_mine_keeps_playing() {
+        let mut board = Board::from_layout("*.\n..\n", Topology::default()).unwrap();
+        assert_eq!(board.mark((0, 0, 0)).unwrap(), Mark::Flag(1));
+        assert_eq!(board.mark((0, 0, 0)).unwrap(), Mark::Question);
+        assert_eq!(board.expose((0, 0, 0)).unwrap(), GameState::Playing);
+        assert!(!board.tile((0, 0, 0)).unwrap().exposed);
+    }
+
+    #[test]
+    fn games_start_on_the_first_expose() {
+        let mut board = Board::new(
+            Settings::builder()
+                .rows(9)
+                .columns(9)
+                .mines(10)
+                .seed(3)
+                .build(),
+        )
+        .unwrap();
+        assert_eq!(board.state(), GameState::NotStarted);
+
+        // marking a tile doesn't start the game
+        board.flag((0, 4, 4)).unwrap();
+        assert_eq!(board.state(), GameState::NotStarted);
+        assert!(!board.started());
+
+        let state = board.expose((0, 0, 0)).unwrap();
+        assert!(board.started());
+        assert!(matches!(state, GameState::Playing | GameState::Won));
+        assert_eq!(state, board.state());
+    }
+
+    #[test]
+    fn exposing_a_mine_loses_and_ends_the_game() {
+        let mut board = Board::from_layout("o*.\n...\n", Topology::default()).unwrap();
+        assert_eq!(board.state(), GameState::Playing);
+        assert_eq!(board.expose((0, 0, 1)).unwrap(), GameState::Lost((0, 0, 1)));
+        assert!(board.state().is_over());
+        assert!(board.tiles.iter().all(|tile| tile.exposed()));
+        assert!(matches!(
+            board.expose((0, 1, 2)),
+            Err(Error::GameOver(GameState::Lost((0, 0, 1))))
+        ));
+        assert!(matches!(board.flag((0, 1, 2)), Err(Error::GameOver(_))));
+    }
+
+    #[test]
+    fn exposing_every_safe_tile_wins_and_flags_the_mines() {
+        let mut board = Board::from_layout("o*.\n...\n", Topology::default()).unwrap();
+        for coord in [(0, 0, 2), (0, 1, 0), (0, 1, 1)] {
+            assert_eq!(board.expose(coord).unwrap(), GameState::Playing);
+        }
+        assert_eq!(board.expose((0, 1, 2)).unwrap(), GameState::Won);
+        assert!(board.tile((0, 0, 1)).unwrap().flagged());
+        assert!(matches!(
+            board.chord((0, 0, 0)),
+            Err(Error::GameOver(GameState::Won))
+        ));
+    }
+
+    #[test]
+    fn undo_and_redo_a_cascade() {
+        let mut board = Board::from_layout("....\n....\n...*\n", Topology::default()).unwrap();
+        assert!(!board.undo());
+
+        board.expose((0, 0, 0)).unwrap();
+        let exposed = (0..board.tiles.len())
+            .filter(|&index| board.tiles[index].exposed())
+            .collect::<Vec<_>>();
+        assert_eq!(exposed.len(), 11);
+        assert_eq!(board.state(), GameState::Won);
+
+        assert!(board.undo());
+        assert_eq!(board.state(), GameState::Playing);
+        assert!(board.tiles.iter().all(|tile| !tile.exposed()));
+        assert_eq!(board.tile((0, 2, 3)).unwrap().mark, Mark::None);
+        assert!(board.undo_used());
+
+        assert!(board.redo());
+        assert_eq!(board.state(), GameState::Won);
+        for index in exposed {
+            assert!(board.tiles[index].exposed());
+        }
+        assert_eq!(board.tile((0, 2, 3)).unwrap().mark, Mark::Flag(1));
+        assert!(!board.redo());
+    }
+
+    #[test]
+    fn undo_a_loss_and_keep_playing() {
+        let mut board = Board::from_layout("o*.\n...\n", Topology::default()).unwrap();
+        board.flag((0, 1, 1)).unwrap();
+        board.expose((0, 0, 1)).unwrap();
+        assert!(board.state().is_over());
+
+        assert!(board.undo());
+        assert_eq!(board.state(), GameState::Playing);
+        assert!(!board.tile((0, 0, 1)).unwrap().exposed);
+        // losing cleared the flag, which undoing puts back
+        assert_eq!(board.tile((0, 1, 1)).unwrap().mark, Mark::Flag(1));
+        assert_eq!(board.available_flags(), 0);
+
+        assert!(board.undo());
+        assert_eq!(board.tile((0, 1, 1)).unwrap().mark, Mark::None);
+        assert_eq!(board.available_flags(), 1);
+    }
+
+    #[test]
+    fn undoing_the_first_expose_takes_the_mines_away() {
+        let mut board = Board::new(
+            Settings::builder()
+                .rows(9)
+                .columns(9)
+                .mines(10)
+                .seed(5)
+                .build(),
+        )
+        .unwrap();
+        board.expose((0, 4, 4)).unwrap();
+        let layout = board.to_layout().unwrap();
+        let mine = board
+            .coordinates()
+            .find(|&coord| board.tile(coord).unwrap().mined())
+            .unwrap();
+
+        assert!(board.undo());
+        assert!(!board.started());
+        assert_eq!(board.state(), GameState::NotStarted);
+        assert!(board.tiles.iter().all(|tile| !tile.mined()));
+
+        assert!(board.redo());
+        assert!(board.started());
+        assert_eq!(board.to_layout().unwrap(), layout);
+
+        // exposing first again places mines afresh, still never under that tile
+        assert!(board.undo());
+        assert_ne!(board.expose(mine).unwrap(), GameState::Lost(mine));
+        assert!(!board.tile(mine).unwrap().mined());
+    }
+
+    #[test]
+    fn unmarked_copies_leave_out_marks_and_history() {
+        let mut board = Board::from_layout("Fo...\n", Topology::default()).unwrap();
+        // with every flag used, marking goes straight to a question mark
+        assert_eq!(board.mark((0, 0, 4)).unwrap(), Mark::Question);
+        board.expose((0, 0, 2)).unwrap();
+
+        let mut unmarked = board.unmarked();
+        assert!(unmarked.tiles.iter().all(|tile| tile.mark() == Mark::None));
+        assert_eq!(unmarked.available_flags(), 1);
+        assert!(unmarked.tile((0, 0, 2)).unwrap().exposed);
+        assert!(!unmarked.undo());
+
+        assert_eq!(board.tile((0, 0, 0)).unwrap().mark, Mark::Flag(1));
+        assert_eq!(board.tile((0, 0, 4)).unwrap().mark, Mark::Question);
+        assert!(board.undo());
+    }
+
+    #[test]
+    fn new_actions_drop_undone_ones() {
+        let mut board = Board::from_layout("o*.\n...\n", Topology::default()).unwrap();
+        board.flag((0, 0, 1)).unwrap();
+        assert!(board.undo());
+        board.mark((0, 0, 2)).unwrap();
+        assert!(!board.redo());
+        assert_eq!(board.tile((0, 0, 1)).unwrap().mark, Mark::None);
+    }
+
+    #[test]
+    fn actions_that_change_nothing_are_not_recorded() {
+        let mut board = Board::from_layout("o*.\n...\n", Topology::default()).unwrap();
+        // an exposed tile can't be flagged or exposed again
+        board.flag((0, 0, 0)).unwrap();
+        board.expose((0, 0, 0)).unwrap();
+        assert!(!board.undo());
+    }
+
+    #[test]
+    fn numbers_count_mines_in_other_layers() {
+        let board =
+            Board::from_layout("o.\n..\n\n.*\n..\n\n*.\n..\n", Topology::default()).unwrap();
+        assert_eq!((board.layers, board.rows, board.columns), (3, 2, 2));
+        assert_eq!(board.adjacent_mines((0, 0, 0)).unwrap(), 1);
+        assert_eq!(board.adjacent_mines((1, 1, 1)).unwrap(), 2);
+    }
+
+    #[test]
+    fn layouts_need_layers_of_equal_height() {
+        assert!(matches!(
+            Board::from_layout("..\n..\n\n..\n", Topology::default()),
+            Err(Error::UnevenLayers {
+                line: 4,
+                expected: 2,
+                found: 1,
+            })
+        ));
+        assert!(matches!(
+            Board::from_layout("..\n..\n\n..\n\n..\n..\n", Topology::default()),
+            Err(Error::UnevenLayers {
+                line: 5,
+                expected: 2,
+                found: 1,
+            })
+        ));
+    }
+
+    #[test]
+    fn layout_errors_list_every_tile() {
+        let Err(error) = Board::from_layout(".x\n", Topology::default()) else {
+            panic!("parsed a layout with an unknown tile");
+        };
+        let message = error.to_string();
+        assert!(message.contains("line 1, column 2"));
+        for tile in [".", "*", "2`-`9", "o", "f", "F", "#", "!", "?"] {
+            assert!(message.contains(&format!("`{tile}`")), "{message}");
+        }
+    }
+
+    #[test]
+    fn masked_tiles_hold_no_mines_and_stop_cascades() {
+        let mask = Mask::parse("...#.\n...#.\n...#.\n").unwrap();
+        let mut board = Board::new(
+            Settings::builder()
+                .rows(3)
+                .columns(5)
+                .mines(2)
+                .mask(Some(mask))
+                .seed(5)
+                .build(),
+        )
+        .unwrap();
+        assert_eq!(board.coordinates().count(), 12);
+        assert!(!board.contains((0, 1, 3)));
+        assert!(matches!(board.tile((0, 1, 3)), Err(Error::GetTile(_))));
+
+        board.expose((0, 1, 1)).unwrap();
+        let mined = board
+            .coordinates()
+            .filter(|&coord| board.tile(coord).unwrap().mined())
+            .count();
+        assert_eq!(mined, 2);
+        // the gap cuts the last column off from everything else
+        assert!(board.adjacent((0, 1, 4)).all(|(_, _, column)| column == 4));
+    }
+
+    #[test]
+    fn masks_must_fit_the_board() {
+        let settings = Settings::builder()
+            .rows(3)
+            .columns(3)
+            .mines(1)
+            .mask(Some(Mask::parse("..\n..\n").unwrap()))
+            .seed(0)
+            .build();
+        assert!(matches!(
+            Board::new(settings),
+            Err(Error::MaskSize {
+                mask_rows: 2,
+                mask_columns: 2,
+                ..
+            })
+        ));
+    }
+
+    #[test]
+    fn interior_offsets_match_the_topology() {
+        for (grid, neighbourhoods) in [
+            (
+                Grid::Square,
+                &[
+                    Neighbourhood::Standard,
+                    Neighbourhood::Orthogonal,
+                    Neighbourhood::Knight,
+                    Neighbourhood::Radius2,
+                ][..],
+            ),
+            (
+                Grid::Hex,
+                &[Neighbourhood::Standard, Neighbourhood::Radius2][..],
+            ),
+        ] {
+            for &neighbourhood in neighbourhoods {
+                for wrap in [Wrap::None, Wrap::Torus] {
+                    let topology = Topology {
+                        grid,
+                        wrap,
+                        neighbourhood,
+                    };
+                    let board = Board::new(
+                        Settings::builder()
+                            .layers(3)
+                            .rows(8)
+                            .columns(9)
+                            .mines(1)
+                            .topology(topology)
+                            .seed(0)
+                            .build(),
+                    )
+                    .unwrap();
+                    for coord in board.coordinates() {
+                        let mut fast = board.adjacent(coord).collect::<Vec<_>>();
+                        fast.sort_unstable();
+                        let slow = topology.adjacent(coord, 3, 8, 9).collect::<Vec<_>>();
+                        assert_eq!(fast, slow, "{topology:?} at {coord:?}");
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn large_cascades_undo_in_full() {
+        let mut board = Board::new(
+            Settings::builder()
+                .rows(300)
+                .columns(300)
+                .mines(1)
+                .seed(9)
+                .build(),
+        )
+        .unwrap();
+        board.expose((0, 150, 150)).unwrap();
+        assert_eq!(board.state(), GameState::Won);
+
+        // the cascade is recorded a bit per tile, which undoing reads back
+        assert!(matches!(
+            board.history.last().map(|action| &action.changes.exposures),
+            Some(Exposures::Dense(_))
+        ));
+        assert!(board.undo());
+        assert!(board
+            .coordinates()
+            .all(|coord| !board.tile(coord).unwrap().exposed));
     }
 }
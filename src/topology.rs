@@ -0,0 +1,564 @@
+use crate::{error::Error, sweep::Coordinate};
+use serde::{Deserialize, Serialize};
+use std::{fmt, str::FromStr};
+
+/// The shape of the tiles on a board.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub(crate) enum Grid {
+    /// Square tiles, each with up to eight neighbours.
+    #[default]
+    Square,
+    /// Hexagonal tiles, each with up to six neighbours. Odd rows are shifted half a tile to the
+    /// right of even rows.
+    Hex,
+}
+
+/// Which edges of a board join up with each other.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub(crate) enum Wrap {
+    /// Every edge is a dead end.
+    #[default]
+    None,
+    /// The left and right edges join.
+    Cylinder,
+    /// The left and right edges join, and so do the top and bottom.
+    Torus,
+    /// The left and right edges join with a flip, so leaving the right edge near the top enters
+    /// the left edge near the bottom.
+    Mobius,
+}
+
+impl Wrap {
+    /// Whether the left and right edges join.
+    pub(crate) fn horizontal(self) -> bool {
+        self != Self::None
+    }
+
+    /// Whether the top and bottom edges join.
+    pub(crate) fn vertical(self) -> bool {
+        self == Self::Torus
+    }
+}
+
+/// Which tiles a number counts the mines of, and so which tiles an expose cascades into.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub(crate) enum Neighbourhood {
+    /// Every tile touching the tile.
+    #[default]
+    Standard,
+    /// Only the tiles sharing an edge with a square tile.
+    Orthogonal,
+    /// The tiles a chess knight could move to from a square tile.
+    Knight,
+    /// Every tile at most two steps away.
+    #[serde(rename = "radius-2")]
+    Radius2,
+}
+
+/// How the tiles of a board fit together, which decides which tiles are adjacent.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub(crate) struct Topology {
+    pub(crate) grid: Grid,
+    pub(crate) wrap: Wrap,
+    pub(crate) neighbourhood: Neighbourhood,
+}
+
+/// A direction to move the cursor in.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Heading {
+    Up,
+    Down,
+    Left,
+    Right,
+    UpLeft,
+    UpRight,
+    DownLeft,
+    DownRight,
+}
+
+const SQUARE_OFFSETS: [(isize, isize); 8] = [
+    (-1, -1),
+    (-1, 0),
+    (-1, 1),
+    (0, -1),
+    (0, 1),
+    (1, -1),
+    (1, 0),
+    (1, 1),
+];
+
+const ORTHOGONAL_OFFSETS: [(isize, isize); 4] = [(-1, 0), (0, -1), (0, 1), (1, 0)];
+
+const KNIGHT_OFFSETS: [(isize, isize); 8] = [
+    (-2, -1),
+    (-2, 1),
+    (-1, -2),
+    (-1, 2),
+    (1, -2),
+    (1, 2),
+    (2, -1),
+    (2, 1),
+];
+
+const EVEN_HEX_OFFSETS: [(isize, isize); 6] = [(-1, -1), (-1, 0), (0, -1), (0, 1), (1, -1), (1, 0)];
+
+const ODD_HEX_OFFSETS: [(isize, isize); 6] = [(-1, 0), (-1, 1), (0, -1), (0, 1), (1, 0), (1, 1)];
+
+impl Topology {
+    /// Check that the topology makes sense for a board with `rows` rows.
+    ///
+    /// Hex rows alternate between two shifts, so joining the top and bottom only lines up with an
+    /// even number of rows, and flipping the rows would shift them the wrong way. Hex tiles have
+    /// no corners to leave out or knight's moves to make.
+    pub(crate) fn validate(self, rows: usize) -> Result<(), Error> {
+        match (self.grid, self.wrap, self.neighbourhood) {
+            (Grid::Hex, Wrap::Torus, _) if rows % 2 == 1 => Err(Error::OddHexTorus(rows)),
+            (Grid::Hex, Wrap::Mobius, _) => Err(Error::HexMobius),
+            (Grid::Hex, _, neighbourhood @ (Neighbourhood::Orthogonal | Neighbourhood::Knight)) => {
+                Err(Error::HexNeighbourhood(neighbourhood))
+            }
+            _ => Ok(()),
+        }
+    }
+
+    /// The row and column offsets from a tile in `row` to each of the tiles touching it.
+    fn offsets(self, row: usize) -> &'static [(isize, isize)] {
+        match self.grid {
+            Grid::Square => &SQUARE_OFFSETS,
+            Grid::Hex if row % 2 == 1 => &ODD_HEX_OFFSETS,
+            Grid::Hex => &EVEN_HEX_OFFSETS,
+        }
+    }
+
+    /// The tiles touching `(row, column)` within its layer.
+    fn touching(
+        self,
+        (row, column): (usize, usize),
+        rows: usize,
+        columns: usize,
+    ) -> impl Iterator<Item = (usize, usize)> {
+        self.offsets(row)
+            .iter()
+            .filter_map(move |&offset| self.offset_coord((row, column), offset, rows, columns))
+    }
+
+    /// The tiles whose mines a number at `(row, column)` counts within its layer, possibly
+    /// including the tile itself or duplicates on narrow wrapping boards.
+    fn neighbours(
+        self,
+        (row, column): (usize, usize),
+        rows: usize,
+        columns: usize,
+    ) -> Vec<(usize, usize)> {
+        let offsets = |offsets: &'static [(isize, isize)]| {
+            offsets
+                .iter()
+                .filter_map(|&offset| self.offset_coord((row, column), offset, rows, columns))
+                .collect()
+        };
+        match self.neighbourhood {
+            Neighbourhood::Standard => self.touching((row, column), rows, columns).collect(),
+            Neighbourhood::Orthogonal => offsets(&ORTHOGONAL_OFFSETS),
+            Neighbourhood::Knight => offsets(&KNIGHT_OFFSETS),
+            // taking two steps follows the grid's shape and wrapping without special cases
+            Neighbourhood::Radius2 => self
+                .touching((row, column), rows, columns)
+                .flat_map(|coord| std::iter::once(coord).chain(self.touching(coord, rows, columns)))
+                .collect(),
+        }
+    }
+
+    /// The tiles adjacent to `(layer, row, column)` on a board of `layers` layers of `rows` by
+    /// `columns` tiles.
+    ///
+    /// Within a layer, adjacency follows the neighbourhood, the grid and its wrapping. A tile is
+    /// also adjacent to the tile directly above and below it, and to everything adjacent to those
+    /// tiles within their own layers.
+    pub(crate) fn adjacent(
+        self,
+        (layer, row, column): Coordinate,
+        layers: usize,
+        rows: usize,
+        columns: usize,
+    ) -> impl Iterator<Item = Coordinate> {
+        let mut in_layer = self.neighbours((row, column), rows, columns);
+        in_layer.push((row, column));
+
+        let mut adjacent = [layer.checked_sub(1), Some(layer), Some(layer + 1)]
+            .into_iter()
+            .flatten()
+            .filter(|&layer| layer < layers)
+            .flat_map(|layer| {
+                in_layer
+                    .iter()
+                    .map(move |&(row, column)| (layer, row, column))
+            })
+            // on narrow wrapping boards several offsets can reach the same tile, or the tile itself
+            .filter(|&coord| coord != (layer, row, column))
+            .collect::<Vec<_>>();
+        adjacent.sort_unstable();
+        adjacent.dedup();
+        adjacent.into_iter()
+    }
+
+    /// The tile reached by moving from `(layer, row, column)` towards `heading` within its layer,
+    /// if there is one.
+    ///
+    /// On a hex grid, moving straight up or down keeps the column, which zig-zags between the
+    /// up-left and up-right neighbours so the cursor stays in a visual column.
+    pub(crate) fn step(
+        self,
+        (layer, row, column): Coordinate,
+        heading: Heading,
+        rows: usize,
+        columns: usize,
+    ) -> Option<Coordinate> {
+        let odd = row % 2 == 1;
+        let offset = match (self.grid, heading) {
+            (_, Heading::Up) => (-1, 0),
+            (_, Heading::Down) => (1, 0),
+            (_, Heading::Left) => (0, -1),
+            (_, Heading::Right) => (0, 1),
+            (Grid::Square, Heading::UpLeft) => (-1, -1),
+            (Grid::Square, Heading::UpRight) => (-1, 1),
+            (Grid::Square, Heading::DownLeft) => (1, -1),
+            (Grid::Square, Heading::DownRight) => (1, 1),
+            (Grid::Hex, Heading::UpLeft) => (-1, isize::from(odd) - 1),
+            (Grid::Hex, Heading::UpRight) => (-1, isize::from(odd)),
+            (Grid::Hex, Heading::DownLeft) => (1, isize::from(odd) - 1),
+            (Grid::Hex, Heading::DownRight) => (1, isize::from(odd)),
+        };
+        let (row, column) = self.offset_coord((row, column), offset, rows, columns)?;
+        Some((layer, row, column))
+    }
+
+    /// Offset `(row, column)` by `(row_offset, column_offset)`, wrapping around joined edges, if
+    /// the result is on the board.
+    fn offset_coord(
+        self,
+        (row, column): (usize, usize),
+        (row_offset, column_offset): (isize, isize),
+        rows: usize,
+        columns: usize,
+    ) -> Option<(usize, usize)> {
+        let mut row = wrap_or_clip(row, row_offset, rows, self.wrap.vertical())?;
+        let column = match column.checked_add_signed(column_offset) {
+            Some(column) if column < columns => column,
+            _ => {
+                let wrapped = wrap_or_clip(column, column_offset, columns, self.wrap.horizontal())?;
+                if self.wrap == Wrap::Mobius {
+                    row = rows - 1 - row;
+                }
+                wrapped
+            }
+        };
+        Some((row, column))
+    }
+}
+
+/// Offset `value` by `offset` within `0..len`, wrapping around the ends if `wrap` is set.
+fn wrap_or_clip(value: usize, offset: isize, len: usize, wrap: bool) -> Option<usize> {
+    if wrap {
+        let len = isize::try_from(len).ok()?;
+        let value = isize::try_from(value).ok()?;
+        usize::try_from((value + offset).rem_euclid(len)).ok()
+    } else {
+        value
+            .checked_add_signed(offset)
+            .filter(|&value| value < len)
+    }
+}
+
+impl fmt::Display for Grid {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Self::Square => "square",
+            Self::Hex => "hex",
+        })
+    }
+}
+
+impl FromStr for Grid {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "square" => Ok(Self::Square),
+            "hex" => Ok(Self::Hex),
+            _ => Err(format!("unknown grid {s:?}, expected `square` or `hex`")),
+        }
+    }
+}
+
+impl Neighbourhood {
+    /// What a number counts, for the help text.
+    pub(crate) fn description(self) -> &'static str {
+        match self {
+            Self::Standard => "touching tiles",
+            Self::Orthogonal => "tiles sharing an edge",
+            Self::Knight => "knight's moves away",
+            Self::Radius2 => "tiles up to 2 steps away",
+        }
+    }
+}
+
+impl fmt::Display for Neighbourhood {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Self::Standard => "standard",
+            Self::Orthogonal => "orthogonal",
+            Self::Knight => "knight",
+            Self::Radius2 => "radius-2",
+        })
+    }
+}
+
+impl FromStr for Neighbourhood {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "standard" => Ok(Self::Standard),
+            "orthogonal" => Ok(Self::Orthogonal),
+            "knight" => Ok(Self::Knight),
+            "radius-2" => Ok(Self::Radius2),
+            _ => Err(format!(
+                "unknown neighbourhood {s:?}, expected `standard`, `orthogonal`, `knight` or `radius-2`"
+            )),
+        }
+    }
+}
+
+impl fmt::Display for Wrap {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Self::None => "none",
+            Self::Cylinder => "cylinder",
+            Self::Torus => "torus",
+            Self::Mobius => "mobius",
+        })
+    }
+}
+
+impl FromStr for Wrap {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "none" => Ok(Self::None),
+            "cylinder" => Ok(Self::Cylinder),
+            "torus" => Ok(Self::Torus),
+            "mobius" => Ok(Self::Mobius),
+            _ => Err(format!(
+                "unknown wrap {s:?}, expected `none`, `cylinder`, `torus` or `mobius`"
+            )),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const HEX: Topology = Topology {
+        grid: Grid::Hex,
+        wrap: Wrap::None,
+        neighbourhood: Neighbourhood::Standard,
+    };
+
+    /// The tiles adjacent to `(row, column)` on a single layer, by row and column.
+    fn adjacent(topology: Topology, (row, column): (usize, usize)) -> Vec<(usize, usize)> {
+        topology
+            .adjacent((0, row, column), 1, 6, 6)
+            .map(|(_, row, column)| (row, column))
+            .collect()
+    }
+
+    fn wrap(wrap: Wrap) -> Topology {
+        Topology {
+            wrap,
+            ..Topology::default()
+        }
+    }
+
+    #[test]
+    fn square_corners_have_three_neighbours() {
+        assert_eq!(
+            adjacent(Topology::default(), (0, 0)),
+            [(0, 1), (1, 0), (1, 1)]
+        );
+        assert_eq!(adjacent(Topology::default(), (2, 2)).len(), 8);
+    }
+
+    #[test]
+    fn hex_rows_shift_their_neighbours() {
+        assert_eq!(
+            adjacent(HEX, (2, 2)),
+            [(1, 1), (1, 2), (2, 1), (2, 3), (3, 1), (3, 2)]
+        );
+        assert_eq!(
+            adjacent(HEX, (3, 2)),
+            [(2, 2), (2, 3), (3, 1), (3, 3), (4, 2), (4, 3)]
+        );
+    }
+
+    #[test]
+    fn hex_steps_zig_zag_up_a_column() {
+        assert_eq!(HEX.step((0, 3, 2), Heading::Up, 6, 6), Some((0, 2, 2)));
+        assert_eq!(HEX.step((0, 3, 2), Heading::UpRight, 6, 6), Some((0, 2, 3)));
+        assert_eq!(HEX.step((0, 2, 2), Heading::UpLeft, 6, 6), Some((0, 1, 1)));
+        assert_eq!(HEX.step((0, 0, 0), Heading::UpLeft, 6, 6), None);
+    }
+
+    #[test]
+    fn hex_grids_reject_what_they_cannot_draw() {
+        let hex = |wrap, neighbourhood| Topology {
+            grid: Grid::Hex,
+            wrap,
+            neighbourhood,
+        };
+        assert!(hex(Wrap::Torus, Neighbourhood::Standard)
+            .validate(6)
+            .is_ok());
+        assert!(matches!(
+            hex(Wrap::Torus, Neighbourhood::Standard).validate(5),
+            Err(Error::OddHexTorus(5))
+        ));
+        assert!(matches!(
+            hex(Wrap::Mobius, Neighbourhood::Standard).validate(6),
+            Err(Error::HexMobius)
+        ));
+        assert!(matches!(
+            hex(Wrap::None, Neighbourhood::Knight).validate(6),
+            Err(Error::HexNeighbourhood(Neighbourhood::Knight))
+        ));
+        assert!(hex(Wrap::None, Neighbourhood::Radius2).validate(5).is_ok());
+    }
+
+    #[test]
+    fn wrapping_joins_edges() {
+        assert_eq!(
+            adjacent(wrap(Wrap::Cylinder), (0, 0)),
+            [(0, 1), (0, 5), (1, 0), (1, 1), (1, 5)]
+        );
+        assert_eq!(
+            adjacent(wrap(Wrap::Torus), (0, 0)),
+            [
+                (0, 1),
+                (0, 5),
+                (1, 0),
+                (1, 1),
+                (1, 5),
+                (5, 0),
+                (5, 1),
+                (5, 5)
+            ]
+        );
+
+        // leaving the right edge of a mobius strip comes back upside down
+        let mobius = adjacent(wrap(Wrap::Mobius), (1, 5));
+        for coord in [(3, 0), (4, 0), (5, 0)] {
+            assert!(mobius.contains(&coord), "{mobius:?}");
+        }
+        assert!(!mobius.contains(&(1, 0)));
+    }
+
+    #[test]
+    fn narrow_wrapping_boards_count_each_tile_once() {
+        let cylinder = wrap(Wrap::Cylinder);
+        assert_eq!(
+            cylinder.adjacent((0, 0, 0), 1, 2, 1).collect::<Vec<_>>(),
+            [(0, 1, 0)]
+        );
+        assert_eq!(
+            cylinder.adjacent((0, 0, 0), 1, 1, 2).collect::<Vec<_>>(),
+            [(0, 0, 1)]
+        );
+    }
+
+    #[test]
+    fn steps_wrap_too() {
+        assert_eq!(
+            wrap(Wrap::Torus).step((0, 0, 0), Heading::UpLeft, 6, 6),
+            Some((0, 5, 5))
+        );
+        assert_eq!(
+            Topology::default().step((0, 0, 0), Heading::UpLeft, 6, 6),
+            None
+        );
+    }
+
+    #[test]
+    fn grids_and_wraps_parse_from_their_names() {
+        for grid in [Grid::Square, Grid::Hex] {
+            assert_eq!(grid.to_string().parse(), Ok(grid));
+        }
+        for wrap in [Wrap::None, Wrap::Cylinder, Wrap::Torus, Wrap::Mobius] {
+            assert_eq!(wrap.to_string().parse(), Ok(wrap));
+        }
+        assert!("triangle".parse::<Grid>().is_err());
+        assert!("klein".parse::<Wrap>().is_err());
+    }
+
+    #[test]
+    fn layers_neighbour_the_layers_above_and_below() {
+        let topology = Topology::default();
+        assert_eq!(topology.adjacent((1, 1, 1), 3, 3, 3).count(), 26);
+        assert_eq!(topology.adjacent((0, 1, 1), 3, 3, 3).count(), 17);
+
+        let below = topology
+            .adjacent((0, 0, 0), 2, 3, 3)
+            .filter(|&(layer, ..)| layer == 1)
+            .collect::<Vec<_>>();
+        assert_eq!(below, [(1, 0, 0), (1, 0, 1), (1, 1, 0), (1, 1, 1)]);
+    }
+
+    #[test]
+    fn neighbourhoods_choose_what_numbers_count() {
+        let square = |neighbourhood| Topology {
+            neighbourhood,
+            ..Topology::default()
+        };
+        assert_eq!(
+            adjacent(square(Neighbourhood::Orthogonal), (2, 2)),
+            [(1, 2), (2, 1), (2, 3), (3, 2)]
+        );
+        assert_eq!(
+            adjacent(square(Neighbourhood::Knight), (2, 2)),
+            [
+                (0, 1),
+                (0, 3),
+                (1, 0),
+                (1, 4),
+                (3, 0),
+                (3, 4),
+                (4, 1),
+                (4, 3)
+            ]
+        );
+        assert_eq!(adjacent(square(Neighbourhood::Radius2), (2, 2)).len(), 24);
+        assert_eq!(adjacent(square(Neighbourhood::Radius2), (0, 0)).len(), 8);
+
+        let hex = Topology {
+            neighbourhood: Neighbourhood::Radius2,
+            ..HEX
+        };
+        assert_eq!(adjacent(hex, (2, 2)).len(), 18);
+    }
+
+    #[test]
+    fn neighbourhoods_parse_from_their_names() {
+        for neighbourhood in [
+            Neighbourhood::Standard,
+            Neighbourhood::Orthogonal,
+            Neighbourhood::Knight,
+            Neighbourhood::Radius2,
+        ] {
+            assert_eq!(neighbourhood.to_string().parse(), Ok(neighbourhood));
+        }
+        assert!("bishop".parse::<Neighbourhood>().is_err());
+    }
+}
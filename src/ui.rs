@@ -1,55 +1,54 @@
 use crate::{
     error::Error,
     events::{Event, Events},
-    sweep::{Board, Coordinate},
+    keymap::{Action, Keymap},
+    menu,
+    probability::Probabilities,
+    solver,
+    sweep::{Board, Coordinate, GameState, Mark, Settings},
+    topology::{Grid, Heading, Wrap},
+    viewport::Viewport,
 };
 use num_traits::ToPrimitive;
 use ratatui::{
     backend::TermionBackend,
-    layout::{Alignment, Constraint, Direction, Layout, Rect},
+    buffer::Buffer,
+    layout::{Alignment, Constraint, Direction, Layout, Margin, Rect},
     style::{Color, Modifier, Style},
-    text::Span,
-    widgets::{Block, BorderType, Borders, Clear, Gauge, List, ListItem, Paragraph},
+    text::{Line, Span},
+    widgets::{
+        block::{Position, Title},
+        Block, BorderType, Borders, Clear, Gauge, List, ListItem, Paragraph, Widget,
+    },
     Terminal,
 };
+use serde::{Deserialize, Serialize};
 use std::{
-    fmt, io,
+    fmt,
+    io::{self, Write},
+    path::{Path, PathBuf},
+    rc::Rc,
+    str::FromStr,
     sync::{
         atomic::{AtomicBool, Ordering},
         Arc,
     },
 };
-use termion::{event::Key, input::MouseTerminal, raw::IntoRawMode, screen::IntoAlternateScreen};
-
-fn centered_rect(width: u16, height: u16, r: Rect) -> Rect {
-    let Rect {
-        width: grid_width,
-        height: grid_height,
-        ..
-    } = r;
-    let popup_layout = Layout::default()
-        .direction(Direction::Vertical)
-        .constraints(
-            [
-                Constraint::Length(grid_height / 2 - height / 2),
-                Constraint::Length(height),
-                Constraint::Length(grid_height / 2 - height / 2),
-            ]
-            .as_ref(),
-        )
-        .split(r);
+use termion::{
+    event::{self, MouseButton, MouseEvent},
+    input::MouseTerminal,
+    raw::{IntoRawMode, RawTerminal},
+    screen::{AlternateScreen, IntoAlternateScreen},
+};
 
-    Layout::default()
-        .direction(Direction::Horizontal)
-        .constraints(
-            [
-                Constraint::Length(grid_width / 2 - width / 2),
-                Constraint::Length(width),
-                Constraint::Length(grid_width / 2 - width / 2),
-            ]
-            .as_ref(),
-        )
-        .split(popup_layout[1])[1]
+/// A `width` by `height` rect centred on `r`, which may spill over its edges when `r` is smaller.
+pub(crate) fn centered_rect(width: u16, height: u16, r: Rect) -> Rect {
+    Rect {
+        x: (r.x + r.width / 2).saturating_sub(width / 2),
+        y: (r.y + r.height / 2).saturating_sub(height / 2),
+        width,
+        height,
+    }
 }
 
 fn align_strings_to_char(strings: &[&str], c: char) -> Vec<String> {
@@ -68,88 +67,808 @@ fn align_strings_to_char(strings: &[&str], c: char) -> Vec<String> {
 
 #[derive(typed_builder::TypedBuilder)]
 pub(crate) struct Ui {
-    rows: usize,
-    columns: usize,
-    mines: usize,
-    cell_width: usize,
-    cell_height: usize,
+    /// The board of the first game, or none to choose one from the menu.
+    #[builder(default)]
+    board: Option<Board>,
+    /// The settings the menu starts new games from.
+    settings: Settings,
+    /// The width of each cell, fitting the cells to the terminal if neither the width nor the
+    /// height is given.
+    #[builder(default)]
+    cell_width: Option<usize>,
+    /// The height of each cell, fitting the cells to the terminal if neither the width nor the
+    /// height is given.
+    #[builder(default)]
+    cell_height: Option<usize>,
+    /// Where to save the board's layout when asked to.
+    #[builder(default)]
+    save_path: Option<PathBuf>,
+    /// Whether marking a tile cycles through a question mark after the flag.
+    #[builder(default = true)]
+    question_marks: bool,
+    /// How many tiles to keep between the active cell and the edges of the view when scrolling a
+    /// board too big to fit on screen.
+    #[builder(default = 2)]
+    scroll_margin: usize,
+    /// How to draw the tiles until toggled to another renderer.
+    #[builder(default)]
+    render: Render,
+    #[builder(default)]
+    keymap: Keymap,
+    #[builder(default)]
+    theme: Theme,
+    #[builder(default)]
+    glyphs: Glyphs,
+}
+
+/// The lines of the help overlay: what each group of actions does, and whether to list the keys
+/// of the group a set at a time like `h j k l / ← ↓ ↑ →` rather than an action at a time.
+const HELP: &[(&str, &[Action], bool)] = &[
+    (
+        "movement",
+        &[Action::Left, Action::Down, Action::Up, Action::Right],
+        true,
+    ),
+    (
+        "diagonals",
+        &[
+            Action::UpLeft,
+            Action::UpRight,
+            Action::DownLeft,
+            Action::DownRight,
+        ],
+        true,
+    ),
+    (
+        "pan a page",
+        &[
+            Action::PanLeft,
+            Action::PanDown,
+            Action::PanUp,
+            Action::PanRight,
+        ],
+        true,
+    ),
+    ("layers", &[Action::PreviousLayer, Action::NextLayer], true),
+    ("expose tile", &[Action::Expose], false),
+    ("flag tile", &[Action::Flag], false),
+    ("remove flag", &[Action::Unflag], false),
+    ("expose neighbours", &[Action::ExposeNeighbours], false),
+    ("flag neighbours", &[Action::FlagNeighbours], false),
+    ("undo / redo", &[Action::Undo, Action::Redo], false),
+    ("mine chances", &[Action::MineChances], false),
+    ("hint", &[Action::Hint], false),
+    ("renderer", &[Action::SwitchRenderer], false),
+    (
+        "zoom in / out / fit",
+        &[Action::ZoomIn, Action::ZoomOut, Action::Fit],
+        false,
+    ),
+    ("save board", &[Action::SaveBoard], false),
+    ("new game", &[Action::NewGame], false),
+    ("help", &[Action::Help], false),
+    ("quit", &[Action::Quit], false),
+];
+
+/// The keys bound to `actions`, a set or an action at a time, with a dot standing in for an
+/// action missing from a set.
+fn help_keys(keymap: &Keymap, actions: &[Action], sets: bool) -> String {
+    let keys = actions
+        .iter()
+        .map(|&action| {
+            keymap
+                .keys(action)
+                .map(|key| key.to_string())
+                .collect::<Vec<_>>()
+        })
+        .collect::<Vec<_>>();
+    if sets {
+        let count = keys.iter().map(Vec::len).max().unwrap_or(0);
+        (0..count)
+            .map(|set| {
+                keys.iter()
+                    .map(|keys| keys.get(set).map_or("·", String::as_str))
+                    .collect::<Vec<_>>()
+                    .join(" ")
+            })
+            .collect::<Vec<_>>()
+            .join(" / ")
+    } else {
+        keys.iter()
+            .map(|keys| keys.join(" "))
+            .collect::<Vec<_>>()
+            .join(" / ")
+    }
+}
+
+/// The size of each cell, in terminal cells.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct CellSize {
+    width: usize,
+    height: usize,
+}
+
+impl CellSize {
+    /// The smallest cell with room for its borders and a character of text.
+    const MIN: Self = Self {
+        width: 3,
+        height: 3,
+    };
+
+    /// The size of a cell when only one of its dimensions is given.
+    const DEFAULT: Self = Self {
+        width: 5,
+        height: 3,
+    };
+
+    /// The size `steps` zoom levels bigger, or smaller for negative steps. Terminal characters
+    /// are about twice as tall as they're wide, so the width grows twice as fast as the height.
+    fn zoom(self, steps: isize) -> Self {
+        Self {
+            width: self
+                .width
+                .saturating_add_signed(2 * steps)
+                .max(Self::MIN.width),
+            height: self
+                .height
+                .saturating_add_signed(steps)
+                .max(Self::MIN.height),
+        }
+    }
+}
+
+/// How the tiles of the board are drawn.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub(crate) enum Render {
+    /// Each tile is a box of its own, sized by the cell width and height.
+    #[default]
+    Blocks,
+    /// The tiles form one continuous grid, neighbouring tiles sharing a border.
+    Grid,
+    /// One character per tile, without any borders.
+    Compact,
+    /// Two rows of tiles per line, each tile a colour without any text.
+    HalfBlock,
+}
+
+impl Render {
+    /// The renderer the toggle key switches to next.
+    fn next(self) -> Self {
+        match self {
+            Self::Blocks => Self::Grid,
+            Self::Grid => Self::Compact,
+            Self::Compact => Self::HalfBlock,
+            Self::HalfBlock => Self::Blocks,
+        }
+    }
+
+    /// How much room the renderer gives each tile with cells of `cell_width` by `cell_height`.
+    fn geometry(self, cell_width: usize, cell_height: usize, hex: bool) -> Geometry {
+        // characters are too narrow to shift by half of one, so single character tiles are
+        // spaced out on hex grids instead
+        let column_step = if hex { 2 } else { 1 };
+        match self {
+            Self::Blocks => Geometry {
+                column_step: cell_width,
+                row_step: cell_height,
+                rows_per_line: 1,
+                border: 0,
+                shift: if hex { cell_width / 2 } else { 0 },
+            },
+            Self::Grid => Geometry {
+                column_step: cell_width - 1,
+                row_step: cell_height - 1,
+                rows_per_line: 1,
+                border: 1,
+                shift: if hex { (cell_width - 1) / 2 } else { 0 },
+            },
+            Self::Compact => Geometry {
+                column_step,
+                row_step: 1,
+                rows_per_line: 1,
+                border: 0,
+                shift: column_step / 2,
+            },
+            Self::HalfBlock => Geometry {
+                column_step,
+                row_step: 1,
+                rows_per_line: 2,
+                border: 0,
+                shift: column_step / 2,
+            },
+        }
+    }
+}
+
+impl fmt::Display for Render {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Self::Blocks => "blocks",
+            Self::Grid => "grid",
+            Self::Compact => "compact",
+            Self::HalfBlock => "half-block",
+        })
+    }
+}
+
+impl FromStr for Render {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "blocks" => Ok(Self::Blocks),
+            "grid" => Ok(Self::Grid),
+            "compact" => Ok(Self::Compact),
+            "half-block" => Ok(Self::HalfBlock),
+            _ => Err(format!(
+                "unknown renderer {s:?}, expected `blocks`, `grid`, `compact` or `half-block`"
+            )),
+        }
+    }
+}
+
+/// The colours the tiles are drawn in.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub(crate) enum Theme {
+    /// Light hidden tiles, opening up onto a dark background.
+    #[default]
+    Dark,
+    /// Dark hidden tiles, opening up onto a light background.
+    Light,
+}
+
+impl Theme {
+    fn palette(self) -> Palette {
+        match self {
+            Self::Dark => Palette::DARK,
+            Self::Light => Palette::LIGHT,
+        }
+    }
+}
+
+impl fmt::Display for Theme {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Self::Dark => "dark",
+            Self::Light => "light",
+        })
+    }
 }
 
-const BOMB: &str = "💣";
-const FLAG: &str = "⛳";
+impl FromStr for Theme {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "dark" => Ok(Self::Dark),
+            "light" => Ok(Self::Light),
+            _ => Err(format!("unknown theme {s:?}, expected `dark` or `light`")),
+        }
+    }
+}
+
+/// The colours of a theme.
+#[derive(Debug, Clone, Copy)]
+struct Palette {
+    /// Behind exposed tiles and borders.
+    background: Color,
+    /// Numbers and plain borders.
+    foreground: Color,
+    hidden: Color,
+    /// The text on hidden tiles, such as flags.
+    hidden_text: Color,
+    /// A numbered tile in the half block renderer, which has no room for the number.
+    number: Color,
+    mine: Color,
+    /// The border of a mine on a lost board.
+    mine_border: Color,
+    /// The mine that lost the game.
+    lost: Color,
+    active: Color,
+    hint: Color,
+    flag: Color,
+    question: Color,
+}
+
+impl Palette {
+    const DARK: Self = Self {
+        background: Color::Black,
+        foreground: Color::White,
+        hidden: Color::White,
+        hidden_text: Color::Black,
+        number: Color::DarkGray,
+        mine: Color::LightYellow,
+        mine_border: Color::LightRed,
+        lost: Color::Red,
+        active: Color::Cyan,
+        hint: Color::LightGreen,
+        flag: Color::LightMagenta,
+        question: Color::Magenta,
+    };
+
+    const LIGHT: Self = Self {
+        background: Color::White,
+        foreground: Color::Black,
+        hidden: Color::DarkGray,
+        hidden_text: Color::White,
+        number: Color::Gray,
+        mine: Color::Red,
+        mine_border: Color::Red,
+        lost: Color::LightRed,
+        active: Color::Blue,
+        hint: Color::Green,
+        flag: Color::Magenta,
+        question: Color::LightMagenta,
+    };
+}
+
+/// The symbols standing in for mines, flags and question marks.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub(crate) enum Glyphs {
+    /// Emoji where there's room for them, which some terminals draw at the wrong width.
+    #[default]
+    Emoji,
+    /// Single width symbols from outside of ASCII.
+    Symbols,
+    /// Plain ASCII characters.
+    Ascii,
+}
+
+impl Glyphs {
+    fn mine(self) -> &'static str {
+        match self {
+            Self::Emoji => "💣",
+            Self::Symbols => "✹",
+            Self::Ascii => "*",
+        }
+    }
+
+    fn flag(self) -> &'static str {
+        match self {
+            Self::Emoji => "⛳",
+            Self::Symbols => "⚑",
+            Self::Ascii => "F",
+        }
+    }
+
+    fn question(self) -> &'static str {
+        match self {
+            Self::Emoji => "❓",
+            Self::Symbols | Self::Ascii => "?",
+        }
+    }
+
+    /// A single character standing in for a mine, where an emoji is too wide.
+    fn mine_char(self) -> char {
+        match self {
+            Self::Symbols => '✹',
+            Self::Emoji | Self::Ascii => '*',
+        }
+    }
+
+    /// A single character standing in for a flag, where an emoji is too wide.
+    fn flag_char(self) -> char {
+        match self {
+            Self::Symbols => '⚑',
+            Self::Emoji | Self::Ascii => 'F',
+        }
+    }
+
+    fn hidden_char(self) -> char {
+        match self {
+            Self::Ascii => '.',
+            Self::Emoji | Self::Symbols => '·',
+        }
+    }
+}
+
+impl fmt::Display for Glyphs {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Self::Emoji => "emoji",
+            Self::Symbols => "symbols",
+            Self::Ascii => "ascii",
+        })
+    }
+}
+
+impl FromStr for Glyphs {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "emoji" => Ok(Self::Emoji),
+            "symbols" => Ok(Self::Symbols),
+            "ascii" => Ok(Self::Ascii),
+            _ => Err(format!(
+                "unknown glyphs {s:?}, expected `emoji`, `symbols` or `ascii`"
+            )),
+        }
+    }
+}
+
+/// How much room a renderer gives each tile, in terminal cells.
+#[derive(Debug, Clone, Copy)]
+struct Geometry {
+    /// The columns between the left edges of neighbouring tiles.
+    column_step: usize,
+    /// The rows between the top edges of neighbouring lines of tiles.
+    row_step: usize,
+    /// How many rows of tiles share a line.
+    rows_per_line: usize,
+    /// The extra row and column closing off the last tiles when tiles share their borders.
+    border: usize,
+    /// How far right odd rows of a hex grid are shifted.
+    shift: usize,
+}
+
+impl Geometry {
+    fn width(&self, columns: usize) -> usize {
+        columns * self.column_step + self.border + self.shift
+    }
+
+    fn height(&self, rows: usize) -> usize {
+        rows.div_ceil(self.rows_per_line) * self.row_step + self.border
+    }
+
+    /// The most columns of tiles that fit in `width` terminal columns.
+    fn columns_in(&self, width: usize) -> usize {
+        width.saturating_sub(self.border + self.shift) / self.column_step
+    }
+
+    /// The most rows of tiles that fit in `height` terminal rows.
+    fn rows_in(&self, height: usize) -> usize {
+        height.saturating_sub(self.border) / self.row_step * self.rows_per_line
+    }
+
+    /// The row and column of the tile drawn at `(x, y)` from the top left corner of the tiles
+    /// in `viewport`, if any.
+    fn tile_at(
+        &self,
+        (x, y): (usize, usize),
+        viewport: &Viewport,
+        hex: bool,
+    ) -> Option<(usize, usize)> {
+        // a line of half blocks can't tell its two rows apart, so take the top one
+        let row = viewport.top + y / self.row_step * self.rows_per_line;
+        let shift = if hex && row % 2 == 1 { self.shift } else { 0 };
+        let column = viewport.left + x.checked_sub(shift)? / self.column_step;
+        (viewport.row_range().contains(&row) && viewport.column_range().contains(&column))
+            .then_some((row, column))
+    }
+}
+
+/// What the last frame drawn looked like, to zoom from and to find the tile under the mouse.
+#[derive(Debug, Clone, Copy)]
+struct Shown {
+    cell_size: CellSize,
+    /// The whole terminal.
+    screen: Rect,
+    /// Where the tiles were drawn.
+    tiles: Rect,
+    geometry: Geometry,
+}
+
+impl Shown {
+    /// The row and column of the tile under the mouse at `(x, y)`, counting from one like mouse
+    /// reports do.
+    fn tile_at(
+        &self,
+        (x, y): (u16, u16),
+        viewport: &Viewport,
+        hex: bool,
+    ) -> Option<(usize, usize)> {
+        let Rect {
+            x: left,
+            y: top,
+            width,
+            height,
+        } = self.tiles;
+        let x = x.checked_sub(1 + left).filter(|&x| x < width)?;
+        let y = y.checked_sub(1 + top).filter(|&y| y < height)?;
+        self.geometry.tile_at((x.into(), y.into()), viewport, hex)
+    }
+}
+
+/// The mouse buttons pressed since they were last all released.
+#[derive(Debug, Default)]
+struct Buttons {
+    left: bool,
+    middle: bool,
+    right: bool,
+}
+
+impl Buttons {
+    fn press(&mut self, button: MouseButton) {
+        match button {
+            MouseButton::Left => self.left = true,
+            MouseButton::Middle => self.middle = true,
+            MouseButton::Right => self.right = true,
+            MouseButton::WheelUp | MouseButton::WheelDown => {}
+        }
+    }
+
+    /// What the buttons pressed do, forgetting them so that releasing any other button pressed
+    /// along with them does nothing.
+    fn release(&mut self) -> Option<Action> {
+        match std::mem::take(self) {
+            // both buttons at once chord, as in the original game
+            Self { middle: true, .. }
+            | Self {
+                left: true,
+                right: true,
+                ..
+            } => Some(Action::ExposeNeighbours),
+            Self { left: true, .. } => Some(Action::Expose),
+            Self { right: true, .. } => Some(Action::Flag),
+            Self { .. } => None,
+        }
+    }
+}
+
+// turn reporting the mouse moving with no button held on and off, which termion's
+// `MouseTerminal` leaves off
+const REPORT_MOTION: &str = "\x1b[?1003h";
+const STOP_REPORTING_MOTION: &str = "\x1b[?1003l";
+
+/// The terminal the game is played on: in raw mode on the alternate screen, reporting the mouse.
+pub(crate) type Screen =
+    Terminal<TermionBackend<MouseTerminal<AlternateScreen<RawTerminal<io::Stdout>>>>>;
+
+/// The height of the flag and mine counters above the board.
+const INFO_HEIGHT: u16 = 3;
+
+/// Convert a size to a number of terminal cells, saturating at the largest a terminal can have.
+pub(crate) fn saturate(size: usize) -> u16 {
+    u16::try_from(size).unwrap_or(u16::MAX)
+}
+
+/// A colour from green for a certainly safe tile through yellow to red for a certain mine.
+fn heat(probability: f64) -> Color {
+    let channel = |value: f64| {
+        (value.clamp(0.0, 1.0) * 255.0)
+            .round()
+            .to_u8()
+            .unwrap_or(u8::MAX)
+    };
+    Color::Rgb(
+        channel(2.0 * probability),
+        channel(2.0 * (1.0 - probability)),
+        0,
+    )
+}
+
+/// A symbol followed by how many times it's stacked on a tile, if more than once.
+fn stacked(symbol: &str, count: u8) -> String {
+    if count > 1 {
+        format!("{symbol}{count}")
+    } else {
+        symbol.to_owned()
+    }
+}
+
+/// The move suggested by the last hint, kept until the board changes.
+struct Hint {
+    /// The exposed tiles whose numbers justify the move.
+    reasons: Vec<Coordinate>,
+    message: String,
+    revision: usize,
+}
+
+/// How far apart two tiles are, ignoring any wrapping.
+fn distance(a: Coordinate, b: Coordinate) -> usize {
+    a.0.abs_diff(b.0) + a.1.abs_diff(b.1) + a.2.abs_diff(b.2)
+}
 
 struct App {
     board: Board,
+    active_layer: usize,
     active_column: usize,
     active_row: usize,
+    /// What happened on the last key press, if it needs saying.
+    status: Option<String>,
+    // the chance of each hidden tile being a mine, while the heat map is shown
+    heat_map: Option<Rc<Probabilities>>,
+    hint: Option<Hint>,
+    // the number of hints asked for this game
+    hints: usize,
+    // the part of the active layer on screen, as of the last frame drawn
+    viewport: Viewport,
+    palette: Palette,
+    glyphs: Glyphs,
 }
 
 struct Cell<'app> {
     app: &'app App,
-    row: usize,
-    column: usize,
+    coord: Coordinate,
+}
+
+/// What a cell's border draws attention to, from least to most important.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+enum Emphasis {
+    None,
+    /// A mine on a lost board.
+    Mine,
+    /// A tile whose number justifies the last hint.
+    HintReason,
+    Active,
+}
+
+impl Emphasis {
+    fn style(self, palette: &Palette) -> Style {
+        let style = Style::default().bg(palette.background);
+        match self {
+            Self::None => style.fg(palette.foreground),
+            Self::Mine => style.fg(palette.mine_border),
+            Self::HintReason => style.fg(palette.hint).add_modifier(Modifier::BOLD),
+            Self::Active => style.fg(palette.active).add_modifier(Modifier::BOLD),
+        }
+    }
+}
+
+// the lines leading off from a point on a grid of shared borders
+const UP: u8 = 1;
+const DOWN: u8 = 2;
+const LEFT: u8 = 4;
+const RIGHT: u8 = 8;
+
+/// The box drawing character joining the lines leading off in `lines`.
+fn junction(lines: u8) -> &'static str {
+    match lines {
+        0 => " ",
+        _ if lines == DOWN | RIGHT => "╭",
+        _ if lines == DOWN | LEFT => "╮",
+        _ if lines == UP | RIGHT => "╰",
+        _ if lines == UP | LEFT => "╯",
+        _ if lines == UP | DOWN | RIGHT => "├",
+        _ if lines == UP | DOWN | LEFT => "┤",
+        _ if lines == LEFT | RIGHT | DOWN => "┬",
+        _ if lines == LEFT | RIGHT | UP => "┴",
+        _ if lines == UP | DOWN | LEFT | RIGHT => "┼",
+        _ if lines & (LEFT | RIGHT) != 0 => "─",
+        _ => "│",
+    }
 }
 
 impl<'app> Cell<'app> {
-    fn new(app: &'app App, row: usize, column: usize) -> Self {
-        Self { app, row, column }
+    fn new(app: &'app App, coord: Coordinate) -> Self {
+        Self { app, coord }
     }
 
     fn is_active(&self) -> bool {
-        self.app.active() == (self.row, self.column)
+        self.app.active() == self.coord
     }
 
     fn is_exposed(&self) -> bool {
-        self.app.board.tile(self.row, self.column).unwrap().exposed
+        self.app.board.tile(self.coord).unwrap().exposed
     }
 
-    fn is_flagged(&self) -> bool {
-        self.app.board.tile(self.row, self.column).unwrap().flagged
+    fn mark(&self) -> Mark {
+        self.app.board.tile(self.coord).unwrap().mark
     }
 
     fn is_mine(&self) -> bool {
-        self.app.board.tile(self.row, self.column).unwrap().mine
+        self.app.board.tile(self.coord).unwrap().mined()
     }
 
-    fn block(&self, lost: bool) -> Block {
+    /// The number of mines on the tile.
+    fn mines(&self) -> u8 {
+        self.app.board.tile(self.coord).unwrap().mines
+    }
+
+    /// The number of mines around the tile.
+    fn adjacent_mines(&self) -> u16 {
+        self.app.board.adjacent_mines(self.coord).unwrap()
+    }
+
+    fn emphasis(&self, lost: bool) -> Emphasis {
+        if self.is_active() {
+            Emphasis::Active
+        } else if self.is_hint_reason() {
+            Emphasis::HintReason
+        } else if lost && self.is_mine() {
+            Emphasis::Mine
+        } else {
+            Emphasis::None
+        }
+    }
+
+    fn block(&self, lost: bool) -> Block<'_> {
         Block::default()
             .borders(Borders::ALL)
-            .style(
-                Style::default()
-                    .bg(Color::Black)
-                    .fg(if self.is_active() {
-                        Color::Cyan
-                    } else if lost && self.is_mine() {
-                        Color::LightRed
-                    } else {
-                        Color::White
-                    })
-                    .add_modifier(if self.is_active() {
-                        Modifier::BOLD
-                    } else {
-                        Modifier::empty()
-                    }),
-            )
+            .style(self.emphasis(lost).style(&self.app.palette))
             .border_type(BorderType::Rounded)
     }
 
+    /// Whether the tile's number justifies the last hint.
+    fn is_hint_reason(&self) -> bool {
+        self.app
+            .hint
+            .as_ref()
+            .is_some_and(|hint| hint.reasons.contains(&self.coord))
+    }
+
+    fn is_losing_mine(&self) -> bool {
+        self.app.state() == GameState::Lost(self.coord)
+    }
+
     fn text_style(&self) -> Style {
+        let palette = &self.app.palette;
         Style::default()
             .fg(if self.is_exposed() && self.is_mine() {
-                Color::LightYellow
+                palette.mine
             } else if self.is_exposed() {
-                Color::White
+                palette.foreground
             } else {
-                Color::Black
+                palette.hidden_text
             })
-            .bg(if self.is_exposed() {
-                Color::Black
+            .bg(if self.is_losing_mine() {
+                palette.lost
+            } else if self.is_exposed() {
+                palette.background
             } else if self.is_active() {
-                Color::Cyan
+                palette.active
+            } else if let Some(&probability) = self.probability() {
+                heat(probability)
             } else {
-                Color::White
+                palette.hidden
             })
     }
+
+    /// The style of a compact cell, which has no border to pick out the active cell or the
+    /// reasons for a hint.
+    fn compact_style(&self, lost: bool) -> Style {
+        let palette = &self.app.palette;
+        match self.emphasis(lost) {
+            Emphasis::Active => self.text_style().fg(palette.background).bg(palette.active),
+            Emphasis::HintReason => self.text_style().fg(palette.background).bg(palette.hint),
+            _ => self.text_style(),
+        }
+    }
+
+    /// The colour filling a half block cell, which has no room for text.
+    fn colour(&self, lost: bool) -> Color {
+        let palette = &self.app.palette;
+        if self.is_losing_mine() {
+            return palette.lost;
+        }
+        match (self.emphasis(lost), self.mark()) {
+            (Emphasis::Active, _) => palette.active,
+            (Emphasis::HintReason, _) => palette.hint,
+            (_, Mark::Flag(_)) => palette.flag,
+            (_, Mark::Question) => palette.question,
+            _ if self.is_exposed() && self.is_mine() => palette.mine,
+            // numbers show as grey, so the edge of an opening stands out
+            _ if self.is_exposed() && self.adjacent_mines() > 0 => palette.number,
+            _ if self.is_exposed() => palette.background,
+            _ => self.probability().map_or(palette.hidden, |&p| heat(p)),
+        }
+    }
+
+    /// The chance of the tile being a mine, if the heat map is shown.
+    fn probability(&self) -> Option<&f64> {
+        self.app.heat_map.as_ref()?.get(&self.coord)
+    }
+
+    /// A single character standing in for the cell, in the compact renderer and the ghosted
+    /// view of a neighbouring layer.
+    fn glyph(&self) -> char {
+        let glyphs = self.app.glyphs;
+        match self.mark() {
+            Mark::Flag(_) => glyphs.flag_char(),
+            Mark::Question => '?',
+            Mark::None if !self.is_exposed() => glyphs.hidden_char(),
+            Mark::None if self.is_mine() => glyphs.mine_char(),
+            Mark::None => match self.adjacent_mines() {
+                0 => ' ',
+                // counts past nine don't fit in a single character
+                n => char::from_digit(n.into(), 10).unwrap_or('+'),
+            },
+        }
+    }
 }
 
 impl fmt::Display for Cell<'_> {
@@ -157,17 +876,14 @@ impl fmt::Display for Cell<'_> {
         write!(
             f,
             "{}",
-            if self.is_flagged() {
-                FLAG.to_owned()
+            if let Mark::Flag(flags) = self.mark() {
+                stacked(self.app.glyphs.flag(), flags)
+            } else if self.mark() == Mark::Question {
+                self.app.glyphs.question().to_owned()
             } else if self.is_mine() && self.is_exposed() {
-                BOMB.to_owned()
+                stacked(self.app.glyphs.mine(), self.mines())
             } else if self.is_exposed() {
-                let num_adjacent_mines = self
-                    .app
-                    .board
-                    .tile(self.row, self.column)
-                    .unwrap()
-                    .adjacent_mines;
+                let num_adjacent_mines = self.adjacent_mines();
                 if num_adjacent_mines == 0 {
                     " ".to_owned()
                 } else {
@@ -181,75 +897,486 @@ impl fmt::Display for Cell<'_> {
 }
 
 impl App {
-    fn new(board: Board) -> Self {
+    fn new(board: Board, theme: Theme, glyphs: Glyphs) -> Self {
+        // the top left corner may be a gap in an irregular board
+        let (active_layer, active_row, active_column) =
+            board.coordinates().next().unwrap_or_default();
         Self {
             board,
-            active_column: 0,
-            active_row: 0,
+            active_layer,
+            active_column,
+            active_row,
+            status: None,
+            heat_map: None,
+            hint: None,
+            hints: 0,
+            viewport: Viewport::default(),
+            palette: theme.palette(),
+            glyphs,
         }
     }
 
-    fn up(&mut self) {
-        if let Some(active_row) = self.active_row.checked_sub(1) {
-            self.active_row = active_row;
+    /// Move the active cell one tile towards `heading`, jumping over gaps and staying put at the
+    /// edge of the board.
+    fn step(&mut self, heading: Heading) {
+        let topology = self.board.topology();
+        let (rows, columns) = (self.board.rows, self.board.columns);
+        let mut coord = self.active();
+        // a wrapping board can lead back around to the start, so stop after crossing it once
+        for _ in 0..rows.max(columns) {
+            let Some(next) = topology.step(coord, heading, rows, columns) else {
+                return;
+            };
+            if self.board.contains(next) {
+                (_, self.active_row, self.active_column) = next;
+                return;
+            }
+            coord = next;
         }
     }
 
-    fn down(&mut self) {
-        self.active_row += usize::from(self.active_row < self.board.rows - 1);
+    /// Move the active cell to the same tile in the layer `offset` layers down, staying put past
+    /// the top or bottom layer.
+    fn step_layer(&mut self, offset: isize) {
+        if let Some(layer) = self
+            .active_layer
+            .checked_add_signed(offset)
+            .filter(|&layer| layer < self.board.layers)
+        {
+            self.active_layer = layer;
+        }
     }
 
-    fn left(&mut self) {
-        if let Some(active_column) = self.active_column.checked_sub(1) {
-            self.active_column = active_column;
+    /// Make the tile at `row` and `column` of the active layer the active cell, unless it's a
+    /// gap.
+    fn point(&mut self, (row, column): (usize, usize)) {
+        if self.board.contains((self.active_layer, row, column)) {
+            self.active_row = row;
+            self.active_column = column;
         }
     }
 
-    fn right(&mut self) {
-        self.active_column += usize::from(self.active_column < self.board.columns - 1);
+    /// Move the active cell a screenful towards `heading`, which scrolls the view along with it.
+    fn pan(&mut self, heading: Heading) {
+        let page = match heading {
+            Heading::Up | Heading::Down => self.viewport.rows,
+            _ => self.viewport.columns,
+        };
+        for _ in 0..page {
+            self.step(heading);
+        }
     }
 
-    fn cell(&self, (r, c): Coordinate) -> Cell {
-        Cell::new(self, r, c)
+    fn cell(&self, coord: Coordinate) -> Cell<'_> {
+        Cell::new(self, coord)
     }
 
-    fn active_cell(&self) -> Cell {
+    fn active_cell(&self) -> Cell<'_> {
         self.cell(self.active())
     }
 
     fn active(&self) -> Coordinate {
-        (self.active_row, self.active_column)
+        (self.active_layer, self.active_row, self.active_column)
+    }
+
+    /// A dimmed one character per tile view of the part of `layer` in line with the viewport,
+    /// with the tile in line with the active cell highlighted.
+    fn ghost_layer(&self, layer: usize, hex: bool) -> Paragraph<'_> {
+        let lines = self
+            .viewport
+            .row_range()
+            .map(|r| {
+                let shift = (hex && r % 2 == 1).then(|| Span::raw(" "));
+                let tiles = self.viewport.column_range().map(move |c| {
+                    let style = if (r, c) == (self.active_row, self.active_column) {
+                        Style::default().fg(Color::Black).bg(Color::DarkGray)
+                    } else {
+                        Style::default().fg(Color::DarkGray)
+                    };
+                    let glyph = if self.board.contains((layer, r, c)) {
+                        self.cell((layer, r, c)).glyph()
+                    } else {
+                        ' '
+                    };
+                    // hex rows are staggered by half a tile, so space the tiles out
+                    let text = if hex {
+                        format!("{glyph} ")
+                    } else {
+                        glyph.to_string()
+                    };
+                    Span::styled(text, style)
+                });
+                Line::from(shift.into_iter().chain(tiles).collect::<Vec<_>>())
+            })
+            .collect::<Vec<_>>();
+
+        Paragraph::new(lines).block(
+            Block::default()
+                .borders(Borders::ALL)
+                .border_type(BorderType::Rounded)
+                .border_style(Style::default().fg(Color::DarkGray))
+                .title(Span::styled(
+                    format!("layer {}", layer + 1),
+                    Style::default().fg(Color::DarkGray),
+                )),
+        )
+    }
+
+    /// Expose the active cell, returning the state of the game it leaves. When no board that can
+    /// be solved without guessing was found, say so rather than quitting, so the player can try
+    /// again.
+    fn expose_active_cell(&mut self) -> Result<GameState, Error> {
+        match self.board.expose(self.active()) {
+            Err(e @ Error::GenerateNoGuessBoard { .. }) => {
+                self.status = Some(e.to_string());
+                Ok(self.board.state())
+            }
+            result => result,
+        }
     }
 
-    fn expose_active_cell(&mut self) -> Result<bool, Error> {
-        self.board.expose(self.active())
+    fn chord_active_cell(&mut self) -> Result<GameState, Error> {
+        self.board.chord(self.active())
     }
 
-    fn expose_all(&mut self) -> Result<(), Error> {
-        self.board.expose_all()
+    fn flag_chord_active_cell(&mut self) -> Result<(), Error> {
+        self.board.flag_chord(self.active())
     }
 
-    fn won(&self) -> bool {
-        self.board.won()
+    fn state(&self) -> GameState {
+        self.board.state()
     }
 
     fn flag_active_cell(&mut self) -> Result<(), Error> {
-        let (r, c) = self.active();
-        self.board.flag(r, c)?;
+        self.board.flag(self.active())?;
+        Ok(())
+    }
+
+    /// Show or hide the chance of each hidden tile being a mine.
+    fn toggle_heat_map(&mut self) -> Result<(), Error> {
+        self.heat_map = match self.heat_map {
+            Some(_) => None,
+            None => Some(self.board.mine_probabilities()?),
+        };
         Ok(())
     }
 
-    pub(crate) fn flag_all(&mut self) {
-        self.board.flag_all()
+    /// Refresh the heat map if it's shown, which the board only recomputes once it has changed.
+    fn update_heat_map(&mut self) -> Result<(), Error> {
+        if self.heat_map.is_some() {
+            self.heat_map = Some(self.board.mine_probabilities()?);
+        }
+        Ok(())
+    }
+
+    /// Move the active cell to the nearest tile that is certainly safe or certainly a mine, or to
+    /// the tile least likely to be a mine if there is none.
+    fn hint(&mut self) -> Result<(), Error> {
+        if !self.board.started() {
+            self.status = Some("hint: any first tile is safe".to_owned());
+            return Ok(());
+        }
+        let active = self.active();
+        // the player's flags may be wrong, so a hint never relies on them
+        let deduction = solver::deduce_from_numbers(&self.board)?
+            .into_iter()
+            // exposing a safe tile makes more progress than flagging a mine
+            .min_by_key(|deduction| (deduction.mines > 0, distance(deduction.coord, active)));
+
+        let (coord, reasons, message) = if let Some(deduction) = deduction {
+            let what = match deduction.mines {
+                0 => "safe".to_owned(),
+                1 => "mine".to_owned(),
+                mines => format!("{mines} mines"),
+            };
+            // without any numbers to point at, the deduction came from the count of mines left
+            let message = if deduction.reasons.is_empty() {
+                format!("hint: {what} (mines left)")
+            } else {
+                format!("hint: {what}")
+            };
+            (deduction.coord, deduction.reasons, message)
+        } else if let Some((&coord, &probability)) = self
+            .board
+            .unmarked()
+            .mine_probabilities()?
+            .iter()
+            .min_by(|(&a, p), (&b, q)| {
+                p.total_cmp(q)
+                    .then_with(|| distance(a, active).cmp(&distance(b, active)))
+            })
+        {
+            let message = format!("hint: no sure move, {:.0}% risk", probability * 100.0);
+            (coord, Vec::new(), message)
+        } else {
+            (active, Vec::new(), "hint: no sure move".to_owned())
+        };
+
+        (self.active_layer, self.active_row, self.active_column) = coord;
+        self.hint = Some(Hint {
+            reasons,
+            message,
+            revision: self.board.revision(),
+        });
+        self.hints += 1;
+        Ok(())
+    }
+
+    /// Drop the last hint once the board has changed since.
+    fn update_hint(&mut self) {
+        let revision = self.board.revision();
+        if matches!(self.hint, Some(Hint { revision: given, .. }) if given != revision) {
+            self.hint = None;
+        }
+    }
+
+    fn unflag_active_cell(&mut self) -> Result<(), Error> {
+        self.board.unflag(self.active())?;
+        Ok(())
+    }
+
+    fn mark_active_cell(&mut self) -> Result<(), Error> {
+        self.board.mark(self.active())?;
+        Ok(())
+    }
+
+    /// Write the board's layout to `path`, saying whether it was written.
+    fn save_board(&mut self, path: &Path) -> Result<(), Error> {
+        let status = if !self.board.started() {
+            "expose a tile first".to_owned()
+        } else {
+            match self.board.to_layout() {
+                Ok(layout) => {
+                    std::fs::write(path, layout)
+                        .map_err(|e| Error::WriteLayout(path.to_owned(), e))?;
+                    format!("saved to {}", path.display())
+                }
+                // a lost board has exposed mines, which a layout can't describe
+                Err(_) if matches!(self.state(), GameState::Lost(_)) => {
+                    "a lost game can't be saved".to_owned()
+                }
+                Err(e) => e.to_string(),
+            }
+        };
+        self.status = Some(status);
+        Ok(())
+    }
+}
+
+/// The tiles of the active layer in view, drawn by one of the renderers.
+struct Tiles<'app> {
+    app: &'app App,
+    render: Render,
+    geometry: Geometry,
+    hex: bool,
+    lost: bool,
+}
+
+impl Tiles<'_> {
+    /// How far right to shift the tiles of `row`.
+    fn shift(&self, row: usize) -> usize {
+        if self.hex && row % 2 == 1 {
+            self.geometry.shift
+        } else {
+            0
+        }
+    }
+
+    /// The visible tiles of the active layer, with their row and column within the view.
+    fn visible(&self) -> impl Iterator<Item = ((usize, usize), Cell<'_>)> + '_ {
+        let viewport = self.app.viewport;
+        viewport.row_range().enumerate().flat_map(move |(y, r)| {
+            viewport
+                .column_range()
+                .enumerate()
+                .map(move |(x, c)| ((y, x), (self.app.active_layer, r, c)))
+                // gaps in an irregular board are left blank
+                .filter(|&(_, coord)| self.app.board.contains(coord))
+                .map(|(at, coord)| (at, self.app.cell(coord)))
+        })
+    }
+
+    /// The text of a cell centred in `width` columns and `height` lines.
+    fn text(cell: &Cell<'_>, width: usize, height: usize) -> String {
+        let single_row_text = format!("{:^width$}", cell.to_string());
+        let pad_line = " ".repeat(width);
+
+        // 1 line for the text, the rest split either side of it
+        let num_pad_lines = height.saturating_sub(1);
+
+        std::iter::repeat_n(pad_line.clone(), num_pad_lines / 2)
+            .chain(std::iter::once(single_row_text))
+            .chain(std::iter::repeat_n(pad_line, num_pad_lines / 2))
+            .collect::<Vec<_>>()
+            .join("\n")
+    }
+
+    /// Each tile in a box of its own.
+    fn blocks(&self, area: Rect, buf: &mut Buffer) {
+        let Geometry {
+            column_step: cell_width,
+            row_step: cell_height,
+            ..
+        } = self.geometry;
+        let viewport = self.app.viewport;
+
+        let row_rects = Layout::default()
+            .direction(Direction::Vertical)
+            .constraints(std::iter::repeat_n(
+                Constraint::Length(saturate(cell_height)),
+                viewport.rows,
+            ))
+            .split(area);
+
+        for (r, row_rect) in viewport.row_range().zip(row_rects.iter()) {
+            let shift = self.shift(r);
+            let col_rects = Layout::default()
+                .direction(Direction::Horizontal)
+                .constraints(
+                    (shift > 0)
+                        .then_some(Constraint::Length(saturate(shift)))
+                        .into_iter()
+                        .chain(std::iter::repeat_n(
+                            Constraint::Length(saturate(cell_width)),
+                            viewport.columns,
+                        ))
+                        // soak up the space left over by the shift of other rows
+                        .chain(self.hex.then_some(Constraint::Min(0))),
+                )
+                .split(*row_rect);
+
+            for (c, cell_rect) in viewport
+                .column_range()
+                .zip(col_rects.iter().skip(usize::from(shift > 0)))
+            {
+                let coord = (self.app.active_layer, r, c);
+                // gaps in an irregular board are left blank
+                if !self.app.board.contains(coord) {
+                    continue;
+                }
+                let cell = self.app.cell(coord);
+                // the top and bottom borders aren't eligible for padding
+                Paragraph::new(Self::text(&cell, cell_width - 2, cell_height - 2))
+                    .block(cell.block(self.lost))
+                    .style(cell.text_style())
+                    .render(*cell_rect, buf);
+            }
+        }
+    }
+
+    /// The tiles in one continuous grid, neighbouring tiles sharing a border.
+    fn grid(&self, area: Rect, buf: &mut Buffer) {
+        let Geometry {
+            column_step,
+            row_step,
+            ..
+        } = self.geometry;
+        let (width, height) = (usize::from(area.width), usize::from(area.height));
+
+        // the lines leading off from each point, and the most important cell they border
+        let mut joins = vec![(0, Emphasis::None); width * height];
+        for ((y, x), cell) in self.visible() {
+            let left = x * column_step + self.shift(cell.coord.1);
+            let top = y * row_step;
+            let (right, bottom) = (left + column_step, top + row_step);
+
+            let emphasis = cell.emphasis(self.lost);
+            let mut join = |x: usize, y: usize, lines: u8| {
+                if x < width && y < height {
+                    let (joined, most) = &mut joins[y * width + x];
+                    *joined |= lines;
+                    *most = emphasis.max(*most);
+                }
+            };
+            for x in left..right {
+                for y in [top, bottom] {
+                    join(x, y, RIGHT);
+                    join(x + 1, y, LEFT);
+                }
+            }
+            for y in top..bottom {
+                for x in [left, right] {
+                    join(x, y, DOWN);
+                    join(x, y + 1, UP);
+                }
+            }
+
+            let inside = Rect {
+                x: area.x + saturate(left + 1),
+                y: area.y + saturate(top + 1),
+                width: saturate(column_step - 1),
+                height: saturate(row_step - 1),
+            }
+            .intersection(area);
+            Paragraph::new(Self::text(&cell, column_step - 1, row_step - 1))
+                .style(cell.text_style())
+                .render(inside, buf);
+        }
+
+        for (i, &(lines, emphasis)) in joins.iter().enumerate() {
+            if lines != 0 {
+                buf.get_mut(area.x + saturate(i % width), area.y + saturate(i / width))
+                    .set_symbol(junction(lines))
+                    .set_style(emphasis.style(&self.app.palette));
+            }
+        }
+    }
+
+    /// One character per tile.
+    fn compact(&self, area: Rect, buf: &mut Buffer) {
+        for ((y, x), cell) in self.visible() {
+            let x = x * self.geometry.column_step + self.shift(cell.coord.1);
+            if x < usize::from(area.width) && y < usize::from(area.height) {
+                buf.get_mut(area.x + saturate(x), area.y + saturate(y))
+                    .set_char(cell.glyph())
+                    .set_style(cell.compact_style(self.lost));
+            }
+        }
+    }
+
+    /// Two rows of tiles per line, the top one in the foreground of an upper half block and the
+    /// bottom one in its background.
+    fn half_blocks(&self, area: Rect, buf: &mut Buffer) {
+        let viewport = self.app.viewport;
+        // the colour of the tile covering column `x` of `row`, if any
+        let colour = |row: usize, x: usize| {
+            let c = x.checked_sub(self.shift(row))? / self.geometry.column_step;
+            let coord = (self.app.active_layer, row, viewport.left + c);
+            (c < viewport.columns && self.app.board.contains(coord))
+                .then(|| self.app.cell(coord).colour(self.lost))
+        };
+
+        let rows = viewport.row_range().collect::<Vec<_>>();
+        for (y, pair) in rows.chunks(2).enumerate().take(usize::from(area.height)) {
+            for x in 0..usize::from(area.width) {
+                let top = colour(pair[0], x);
+                let bottom = pair.get(1).and_then(|&row| colour(row, x));
+                if top.is_none() && bottom.is_none() {
+                    continue;
+                }
+                buf.get_mut(area.x + saturate(x), area.y + saturate(y))
+                    .set_symbol("▀")
+                    .set_fg(top.unwrap_or(Color::Reset))
+                    .set_bg(bottom.unwrap_or(Color::Reset));
+            }
+        }
+    }
+}
+
+impl Widget for Tiles<'_> {
+    fn render(self, area: Rect, buf: &mut Buffer) {
+        match self.render {
+            Render::Blocks => self.blocks(area, buf),
+            Render::Grid => self.grid(area, buf),
+            Render::Compact => self.compact(area, buf),
+            Render::HalfBlock => self.half_blocks(area, buf),
+        }
     }
 }
 
 impl Ui {
-    pub(crate) fn run(&mut self) -> Result<(), Error> {
+    pub(crate) fn run(mut self) -> Result<(), Error> {
         let events = Events::new();
-        let rows = self.rows;
-        let columns = self.columns;
-        let mines = self.mines;
 
         let running = Arc::new(AtomicBool::new(true));
         let running_clone = running.clone();
@@ -259,31 +1386,6 @@ impl Ui {
         })
         .map_err(Error::SetHandler)?;
 
-        let cell_width = self.cell_width;
-        let cell_height = self.cell_height;
-
-        let padding = 1;
-
-        let grid_width =
-            u16::try_from(cell_width * columns + 2 * padding).map_err(Error::ConvertUsizeToU16)?;
-        let grid_height =
-            u16::try_from(cell_height * rows + 2 * padding).map_err(Error::ConvertUsizeToU16)?;
-
-        let row_constraints = std::iter::repeat(Constraint::Length(
-            u16::try_from(cell_height).map_err(Error::ConvertUsizeToU16)?,
-        ))
-        .take(rows)
-        .collect::<Vec<_>>();
-
-        let col_constraints = std::iter::repeat(Constraint::Length(
-            u16::try_from(cell_width).map_err(Error::ConvertUsizeToU16)?,
-        ))
-        .take(columns)
-        .collect::<Vec<_>>();
-
-        let mut app = App::new(Board::new(rows, columns, mines)?);
-        let mut lost = false;
-
         let stdout = io::stdout()
             .into_raw_mode()
             .map_err(Error::GetStdoutInRawMode)?
@@ -292,37 +1394,207 @@ impl Ui {
         let mouse_terminal = MouseTerminal::from(stdout);
         let backend = TermionBackend::new(mouse_terminal);
         let mut terminal = Terminal::new(backend).map_err(Error::CreateTerminal)?;
+        // so that hovering moves the active cell
+        write!(terminal.backend_mut(), "{REPORT_MOTION}").map_err(Error::ReportMouseMotion)?;
+
+        let mut board = self.board.take();
+        let mut settings = self.settings.clone();
+        while running.load(Ordering::SeqCst) {
+            let board = match board.take() {
+                Some(board) => board,
+                None => {
+                    match menu::choose(&mut terminal, &events, &running, &self.keymap, &settings)? {
+                        Some(board) => board,
+                        None => break,
+                    }
+                }
+            };
+            // start the menu from this game's size next time, with the seed after this game's
+            // like simulated games
+            settings.rows = board.rows;
+            settings.columns = board.columns;
+            settings.mines = board.mines();
+            settings.seed = board.seed().unwrap_or(settings.seed).wrapping_add(1);
+            if !self.play(board, &mut terminal, &events, &running)? {
+                break;
+            }
+        }
+
+        write!(terminal.backend_mut(), "{STOP_REPORTING_MOTION}")
+            .map_err(Error::ReportMouseMotion)?;
+        Ok(())
+    }
+
+    /// Play a game of `board` until quitting or asking for a new game, returning whether a new
+    /// game was asked for.
+    fn play(
+        &mut self,
+        board: Board,
+        terminal: &mut Screen,
+        events: &Events,
+        running: &AtomicBool,
+    ) -> Result<bool, Error> {
+        let layers = board.layers;
+        let rows = board.rows;
+        let columns = board.columns;
+        let mines = board.mines();
+        let stacked_flags = board.mines_per_tile() > 1;
+
+        // no size fits the cells to the terminal on every frame, until zoomed
+        let mut cell_size = match (self.cell_width, self.cell_height) {
+            (None, None) => None,
+            (width, height) => Some(CellSize {
+                width: width.unwrap_or(CellSize::DEFAULT.width),
+                height: height.unwrap_or(CellSize::DEFAULT.height),
+            }),
+        };
+        if let Some(CellSize { width, height }) = cell_size {
+            if width < CellSize::MIN.width || height < CellSize::MIN.height {
+                return Err(Error::CellTooSmall { width, height });
+            }
+            // cells are laid out in terminal cells, which are counted in u16s
+            u16::try_from(width.max(height)).map_err(Error::ConvertUsizeToU16)?;
+        }
+        let mut shown = None;
+        let mut buttons = Buttons::default();
+
+        let padding = 1;
+
+        // odd rows of a hex grid are shifted right by half a cell
+        let topology = board.topology();
+        let hex = topology.grid == Grid::Hex;
+        let keymap = &self.keymap;
+        let save_path = self.save_path.as_ref();
+        let question_marks = self.question_marks;
+        let scroll_margin = self.scroll_margin;
+        let mut render = self.render;
+
+        // variant rules change what the numbers mean, so always say which one is in play
+        let mut help_text = vec![format!(
+            "numbers count: {}",
+            topology.neighbourhood.description()
+        )];
+        if keymap.keys(Action::Help).next().is_some() {
+            help_text.push(format!(
+                "help: {}",
+                help_keys(keymap, &[Action::Help], false)
+            ));
+        }
+        let help_text = align_strings_to_char(
+            &help_text.iter().map(String::as_str).collect::<Vec<_>>(),
+            ':',
+        );
+
+        // only the actions that do something on this board, and have keys to do them with
+        let help_overlay = HELP
+            .iter()
+            .filter(|(_, actions, _)| {
+                actions.iter().all(|action| match action {
+                    Action::PreviousLayer | Action::NextLayer => layers > 1,
+                    Action::Unflag => stacked_flags,
+                    Action::SaveBoard => save_path.is_some(),
+                    _ => true,
+                }) && actions
+                    .iter()
+                    .any(|&action| keymap.keys(action).next().is_some())
+            })
+            .map(|&(description, actions, sets)| {
+                let description = if actions == [Action::Flag] && question_marks {
+                    "flag / question tile"
+                } else {
+                    description
+                };
+                format!("{description}: {}", help_keys(keymap, actions, sets))
+            })
+            .chain(std::iter::once(
+                "expose / flag / chord: mouse left / right / both".to_owned(),
+            ))
+            .collect::<Vec<_>>();
+        let help_overlay = align_strings_to_char(
+            &help_overlay.iter().map(String::as_str).collect::<Vec<_>>(),
+            ':',
+        );
+        let mut show_help = false;
+        let mut app = App::new(board, self.theme, self.glyphs);
 
         while running.load(Ordering::SeqCst) {
             terminal
                 .draw(|frame| {
                     let terminal_rect = frame.size();
+                    let state = app.state();
+                    let lost = matches!(state, GameState::Lost(_));
 
                     let outer_block = Block::default()
                         .borders(Borders::ALL)
-                        .title(Span::styled(
-                            "Minesweeper",
-                            Style::default()
-                                .fg(Color::LightYellow)
-                                .add_modifier(Modifier::BOLD),
+                        .title(Line::from(
+                            std::iter::once(Span::styled(
+                                "Minesweeper",
+                                Style::default()
+                                    .fg(Color::LightYellow)
+                                    .add_modifier(Modifier::BOLD),
+                            ))
+                            .chain(app.board.seed().map(|seed| {
+                                Span::styled(
+                                    format!(" (seed: {seed})"),
+                                    Style::default().fg(Color::Gray),
+                                )
+                            }))
+                            .collect::<Vec<_>>(),
                         ))
                         .border_type(BorderType::Rounded);
                     frame.render_widget(outer_block, terminal_rect);
 
-                    let outer_rects = Layout::default()
-                        .direction(Direction::Vertical)
-                        .vertical_margin(1)
-                        .horizontal_margin(1)
-                        .constraints(vec![Constraint::Min(grid_height)])
-                        .split(terminal_rect);
+                    let mines_rect = terminal_rect.inner(&Margin::new(1, 1));
+
+                    // fit as many whole cells as there's room for around the counters above
+                    // the board and the help text below it
+                    let fit = |geometry: Geometry, help_lines: usize| {
+                        let width = geometry
+                            .columns_in(usize::from(mines_rect.width).saturating_sub(2 * padding));
+                        let height =
+                            geometry.rows_in(usize::from(mines_rect.height).saturating_sub(
+                                usize::from(INFO_HEIGHT) + help_lines + 2 * padding,
+                            ));
+                        (height.clamp(1, rows), width.clamp(1, columns))
+                    };
+                    let geometry_of =
+                        |CellSize { width, height }| render.geometry(width, height, hex);
+                    let cell_size = cell_size.unwrap_or_else(|| {
+                        // grow the cells while the whole board still fits, or until they outgrow
+                        // the terminal for renderers that don't draw cells at their size
+                        let mut size = CellSize::MIN;
+                        while size.height < usize::from(mines_rect.height)
+                            && fit(geometry_of(size.zoom(1)), help_text.len()) == (rows, columns)
+                        {
+                            size = size.zoom(1);
+                        }
+                        size
+                    });
+                    let geometry = geometry_of(cell_size);
+
+                    let visible = fit(geometry, help_text.len());
+                    app.viewport.follow(
+                        (app.active_row, app.active_column),
+                        visible,
+                        (rows, columns),
+                        scroll_margin,
+                    );
+                    let (visible_rows, visible_columns) = visible;
 
-                    let mines_rect = outer_rects[0];
+                    let grid_width = saturate(geometry.width(visible_columns) + 2 * padding);
+                    let grid_height = saturate(geometry.height(visible_rows) + 2 * padding);
+                    // small boards leave room either side, so keep the counters and help text
+                    // readable
+                    let column_width = help_text
+                        .iter()
+                        .map(|line| saturate(line.chars().count()))
+                        .fold(grid_width, u16::max);
 
                     let available_flags = app.board.available_flags();
                     let info_text = Gauge::default()
                         .block(
                             Block::default().borders(Borders::ALL).title(Span::styled(
-                                FLAG,
+                                app.glyphs.flag(),
                                 Style::default()
                                     .fg(Color::LightMagenta)
                                     .add_modifier(Modifier::BOLD),
@@ -350,14 +1622,14 @@ impl Ui {
 
                     let horizontal_pad_block_width = terminal_rect
                         .width
-                        .checked_sub(grid_width)
+                        .checked_sub(column_width)
                         .unwrap_or(terminal_rect.width)
                         / 2;
                     let mines_rects = Layout::default()
                         .direction(Direction::Horizontal)
                         .constraints(vec![
                             Constraint::Min(horizontal_pad_block_width),
-                            Constraint::Length(grid_width),
+                            Constraint::Length(column_width),
                             // unclear why the right side padding is much smaller than the rest
                             //
                             // I suspect it's a consequence of the layout algorithm
@@ -367,34 +1639,28 @@ impl Ui {
                         ])
                         .split(mines_rect);
 
-                    let vertical_pad_block_height = mines_rect
-                        .height
-                        .checked_sub(grid_height)
-                        .unwrap_or(mines_rect.height)
-                        / 2;
+                    // centre the board, unless that would squeeze the counters or the help text
+                    let free_height = mines_rect.height.saturating_sub(grid_height);
+                    let vertical_pad_block_height = (free_height / 2)
+                        .max(INFO_HEIGHT)
+                        .min(free_height.saturating_sub(saturate(help_text.len())));
                     let middle_mines_rects = Layout::default()
                         .direction(Direction::Vertical)
                         .constraints(vec![
-                            Constraint::Min(vertical_pad_block_height),
+                            Constraint::Length(vertical_pad_block_height),
                             Constraint::Length(grid_height),
-                            Constraint::Min(vertical_pad_block_height),
+                            Constraint::Min(0),
                         ])
                         .split(mines_rects[1]);
 
                     let help_text_block = List::new(
-                        align_strings_to_char(
-                            &[
-                                "movement: hjkl / ← ↓ ↑ →",
-                                "expose tile: spacebar",
-                                "flag tile: f",
-                                "quit: q",
-                            ],
-                            ':',
-                        )
-                        .into_iter()
-                        .map(|line| format!("{:^width$}", line, width = usize::from(grid_width)))
-                        .map(ListItem::new)
-                        .collect::<Vec<_>>(),
+                        help_text
+                            .iter()
+                            .map(|line| {
+                                format!("{:^width$}", line, width = usize::from(column_width))
+                            })
+                            .map(ListItem::new)
+                            .collect::<Vec<_>>(),
                     )
                     .block(Block::default().borders(Borders::NONE));
                     frame.render_widget(help_text_block, middle_mines_rects[2]);
@@ -402,8 +1668,8 @@ impl Ui {
                     let info_text_split_rects = Layout::default()
                         .direction(Direction::Vertical)
                         .constraints(vec![
-                            Constraint::Min(vertical_pad_block_height - 3),
-                            Constraint::Length(3),
+                            Constraint::Min(vertical_pad_block_height.saturating_sub(INFO_HEIGHT)),
+                            Constraint::Length(INFO_HEIGHT),
                         ])
                         .split(middle_mines_rects[0]);
 
@@ -416,7 +1682,7 @@ impl Ui {
                     let mines_text = Paragraph::new(mines.to_string())
                         .block(
                             Block::default().borders(Borders::ALL).title(Span::styled(
-                                BOMB,
+                                app.glyphs.mine(),
                                 Style::default()
                                     .fg(Color::LightYellow)
                                     .add_modifier(Modifier::BOLD),
@@ -425,64 +1691,165 @@ impl Ui {
                         .alignment(Alignment::Center);
                     frame.render_widget(mines_text, info_mines_rects[1]);
 
-                    let mines_block = Block::default()
+                    let mut mines_block = Block::default()
                         .borders(Borders::ALL)
                         .border_type(BorderType::Rounded);
+                    // a status answers the last action, so it comes before an older hint
+                    let status = app
+                        .status
+                        .as_deref()
+                        .or(app.hint.as_ref().map(|hint| hint.message.as_str()));
+                    if let Some(status) = status {
+                        mines_block = mines_block.title(
+                            Title::from(Span::styled(
+                                status,
+                                Style::default()
+                                    .fg(Color::LightGreen)
+                                    .add_modifier(Modifier::BOLD),
+                            ))
+                            .position(Position::Bottom)
+                            .alignment(Alignment::Center),
+                        );
+                    }
+                    if layers > 1 {
+                        mines_block =
+                            mines_block.title(format!("layer {}/{layers}", app.active_layer + 1));
 
-                    let final_mines_rect = middle_mines_rects[1];
+                        // ghost the layers above and below either side of the board
+                        let ghost_rects = [
+                            (app.active_layer.checked_sub(1), mines_rects[0]),
+                            (
+                                Some(app.active_layer + 1).filter(|&layer| layer < layers),
+                                mines_rects[2],
+                            ),
+                        ];
+                        for (layer, side_rect) in ghost_rects {
+                            let Some(layer) = layer else {
+                                continue;
+                            };
+                            // wide enough for the tiles and the title
+                            let ghost_width = (visible_columns * if hex { 2 } else { 1 })
+                                .max(format!("layer {}", layer + 1).len())
+                                + 2;
+                            let ghost_rect = centered_rect(
+                                ghost_width
+                                    .to_u16()
+                                    .unwrap_or(u16::MAX)
+                                    .min(side_rect.width),
+                                (visible_rows + 2)
+                                    .to_u16()
+                                    .unwrap_or(u16::MAX)
+                                    .min(side_rect.height),
+                                side_rect,
+                            );
+                            frame.render_widget(app.ghost_layer(layer, hex), ghost_rect);
+                        }
+                    }
+
+                    let final_mines_rect =
+                        centered_rect(grid_width, grid_height, middle_mines_rects[1])
+                            .intersection(middle_mines_rects[1]);
                     frame.render_widget(mines_block, final_mines_rect);
 
-                    let row_rects = Layout::default()
-                        .direction(Direction::Vertical)
-                        .vertical_margin(1)
-                        .horizontal_margin(0)
-                        .constraints(row_constraints.clone())
-                        .split(final_mines_rect);
-
-                    for (r, row_rect) in row_rects.iter().enumerate() {
-                        let col_rects = Layout::default()
-                            .direction(Direction::Horizontal)
-                            .vertical_margin(0)
-                            .horizontal_margin(1)
-                            .constraints(col_constraints.clone())
-                            .split(*row_rect);
-
-                        for (c, cell_rect) in col_rects.iter().enumerate() {
-                            let cell = app.cell((r, c));
-                            let single_row_text =
-                                format!("{:^length$}", cell.to_string(), length = cell_width - 2);
-                            let pad_line = " ".repeat(cell_width);
-
-                            // 1 line for the text, 1 line each for the top and bottom of the cell == 3 lines
-                            // that are not eligible for padding
-                            let num_pad_lines = cell_height - 3;
-
-                            // text is:
-                            //   pad with half the pad lines budget
-                            //   the interesting text
-                            //   pad with half the pad lines budget
-                            //   join with newlines
-                            let text = std::iter::repeat(pad_line.clone())
-                                .take(num_pad_lines / 2)
-                                .chain(std::iter::once(single_row_text))
-                                .chain(std::iter::repeat(pad_line).take(num_pad_lines / 2))
-                                .collect::<Vec<_>>()
-                                .join("\n");
-
-                            let cell_text = Paragraph::new(text)
-                                .block(cell.block(lost))
-                                .style(cell.text_style());
-                            frame.render_widget(cell_text, *cell_rect);
+                    // draw edges that wrap around over the plain border, a mobius strip's flip
+                    // gets a heavier line than a plain join
+                    let wrap_border_type = if topology.wrap == Wrap::Mobius {
+                        BorderType::Thick
+                    } else {
+                        BorderType::Double
+                    };
+                    let wrapped_borders = if topology.wrap.horizontal() {
+                        Borders::LEFT | Borders::RIGHT
+                    } else {
+                        Borders::NONE
+                    } | if topology.wrap.vertical() {
+                        Borders::TOP | Borders::BOTTOM
+                    } else {
+                        Borders::NONE
+                    };
+                    frame.render_widget(
+                        Block::default()
+                            .borders(wrapped_borders)
+                            .border_type(wrap_border_type)
+                            .border_style(Style::default().fg(Color::LightBlue)),
+                        final_mines_rect,
+                    );
+
+                    let tiles_rect =
+                        final_mines_rect.inner(&Margin::new(saturate(padding), saturate(padding)));
+                    frame.render_widget(
+                        Tiles {
+                            app: &app,
+                            render,
+                            geometry,
+                            hex,
+                            lost,
+                        },
+                        tiles_rect,
+                    );
+                    shown = Some(Shown {
+                        cell_size,
+                        screen: terminal_rect,
+                        tiles: tiles_rect,
+                        geometry,
+                    });
+
+                    // point out the parts of the board scrolled out of view, at the ends of the
+                    // borders that titles leave free
+                    let Rect {
+                        x,
+                        y,
+                        width,
+                        height,
+                    } = final_mines_rect;
+                    let indicators = [
+                        (app.viewport.top > 0, (x + width.saturating_sub(3), y), "▲"),
+                        (
+                            app.viewport.row_range().end < rows,
+                            (x + width.saturating_sub(3), y + height.saturating_sub(1)),
+                            "▼",
+                        ),
+                        (app.viewport.left > 0, (x, y + height / 2), "◀"),
+                        (
+                            app.viewport.column_range().end < columns,
+                            (x + width.saturating_sub(1), y + height / 2),
+                            "▶",
+                        ),
+                    ];
+                    for (scrolled, (x, y), indicator) in indicators {
+                        if scrolled {
+                            frame.buffer_mut().set_string(
+                                x,
+                                y,
+                                indicator,
+                                Style::default()
+                                    .fg(Color::LightYellow)
+                                    .add_modifier(Modifier::BOLD),
+                            );
                         }
                     }
 
                     // if the user has lost or won, display a banner indicating so
-                    if lost || app.won() {
-                        app.flag_all();
-                        let area = centered_rect(20, 3, final_mines_rect);
+                    if state.is_over() {
+                        let mut banner =
+                            vec![format!("You {}!", if lost { "lose" } else { "won" })];
+                        if app.board.undo_used() {
+                            // undone games don't count, so say so
+                            banner.push("(undo used)".to_owned());
+                        }
+                        match app.hints {
+                            0 => {}
+                            1 => banner.push("(1 hint used)".to_owned()),
+                            hints => banner.push(format!("({hints} hints used)")),
+                        }
+
+                        // one line per message plus the top and bottom borders
+                        let banner_height = (banner.len() + 2).to_u16().unwrap();
+                        let area = centered_rect(20, banner_height, final_mines_rect)
+                            .intersection(terminal_rect);
                         frame.render_widget(Clear, area); // this clears out the background
                         frame.render_widget(
-                            Paragraph::new(format!("You {}!", if lost { "lose" } else { "won" }))
+                            Paragraph::new(banner.join("\n"))
                                 .block(
                                     Block::default()
                                         .borders(Borders::ALL)
@@ -503,29 +1870,342 @@ impl Ui {
                             area,
                         );
                     }
+
+                    if show_help {
+                        // the longest line plus the borders and a space either side
+                        let width = help_overlay
+                            .iter()
+                            .map(|line| line.chars().count() + 4)
+                            .max()
+                            .unwrap_or(0);
+                        let area = centered_rect(
+                            saturate(width),
+                            saturate(help_overlay.len() + 2),
+                            terminal_rect,
+                        )
+                        .intersection(terminal_rect);
+                        frame.render_widget(Clear, area);
+                        frame.render_widget(
+                            List::new(
+                                help_overlay
+                                    .iter()
+                                    .map(|line| ListItem::new(format!(" {line}")))
+                                    .collect::<Vec<_>>(),
+                            )
+                            .block(
+                                Block::default()
+                                    .borders(Borders::ALL)
+                                    .border_type(BorderType::Rounded)
+                                    .border_style(Style::default().fg(Color::LightYellow))
+                                    .title("keys")
+                                    .title(
+                                        Title::from("any key closes")
+                                            .position(Position::Bottom)
+                                            .alignment(Alignment::Center),
+                                    ),
+                            ),
+                            area,
+                        );
+                    }
                 })
                 .map_err(Error::DrawToTerminal)?;
 
-            if let Event::Input(key) = events.next().map_err(Error::GetEvent)? {
-                match key {
-                    // movement using arrow keys or vim movement keys
-                    Key::Up | Key::Char('k') => app.up(),
-                    Key::Down | Key::Char('j') => app.down(),
-                    Key::Left | Key::Char('h') => app.left(),
-                    Key::Right | Key::Char('l') => app.right(),
-                    Key::Char('f') if !lost && !app.won() => app.flag_active_cell()?,
-                    Key::Char(' ') if !lost && !app.won() && !app.active_cell().is_flagged() => {
-                        lost = app.expose_active_cell()?;
-                        if lost {
-                            app.expose_all()?;
+            let action = match events.next().map_err(Error::GetEvent)? {
+                // the help covers the board, so the first key after it only closes it
+                Event::Input(event::Event::Key(_)) if show_help => {
+                    show_help = false;
+                    continue;
+                }
+                Event::Input(event::Event::Key(key)) => match keymap.action(key) {
+                    Some(action) => action,
+                    None => continue,
+                },
+                Event::Input(event::Event::Mouse(mouse)) => {
+                    let (MouseEvent::Press(_, x, y)
+                    | MouseEvent::Release(x, y)
+                    | MouseEvent::Hold(x, y)) = mouse;
+                    let tile = shown.and_then(|shown| shown.tile_at((x, y), &app.viewport, hex));
+                    if let Some(tile) = tile {
+                        app.point(tile);
+                    }
+                    match mouse {
+                        MouseEvent::Press(button, ..) => {
+                            buttons.press(button);
+                            continue;
                         }
+                        MouseEvent::Release(..) => match buttons.release() {
+                            Some(action) if tile.is_some() => action,
+                            _ => continue,
+                        },
+                        MouseEvent::Hold(..) => continue,
                     }
-                    Key::Char('q') => break,
-                    _ => {}
                 }
+                _ => continue,
+            };
+            app.status = None;
+            match action {
+                Action::Up => app.step(Heading::Up),
+                Action::Down => app.step(Heading::Down),
+                Action::Left => app.step(Heading::Left),
+                Action::Right => app.step(Heading::Right),
+                Action::UpLeft => app.step(Heading::UpLeft),
+                Action::UpRight => app.step(Heading::UpRight),
+                Action::DownLeft => app.step(Heading::DownLeft),
+                Action::DownRight => app.step(Heading::DownRight),
+                Action::PanUp => app.pan(Heading::Up),
+                Action::PanDown => app.pan(Heading::Down),
+                Action::PanLeft => app.pan(Heading::Left),
+                Action::PanRight => app.pan(Heading::Right),
+                Action::PreviousLayer => app.step_layer(-1),
+                Action::NextLayer => app.step_layer(1),
+                Action::Flag if !app.state().is_over() => {
+                    if question_marks {
+                        app.mark_active_cell()?
+                    } else {
+                        app.flag_active_cell()?
+                    }
+                }
+                Action::Unflag if !app.state().is_over() => app.unflag_active_cell()?,
+                Action::Expose
+                    if !app.state().is_over() && app.active_cell().mark() == Mark::None =>
+                {
+                    app.expose_active_cell()?;
+                }
+                Action::ExposeNeighbours if !app.state().is_over() => {
+                    app.chord_active_cell()?;
+                }
+                Action::FlagNeighbours if !app.state().is_over() => app.flag_chord_active_cell()?,
+                Action::SaveBoard => {
+                    if let Some(path) = save_path {
+                        app.save_board(path)?;
+                    }
+                }
+                Action::Undo => {
+                    app.board.undo();
+                }
+                Action::Redo => {
+                    app.board.redo();
+                }
+                Action::MineChances => app.toggle_heat_map()?,
+                Action::SwitchRenderer => render = render.next(),
+                // zooming in past the size of the terminal would only show part of a cell
+                Action::ZoomIn => {
+                    if let Some(shown) = shown
+                        .filter(|shown| shown.cell_size.height < usize::from(shown.screen.height))
+                    {
+                        cell_size = Some(shown.cell_size.zoom(1));
+                    }
+                }
+                Action::ZoomOut => {
+                    if let Some(shown) = shown {
+                        cell_size = Some(shown.cell_size.zoom(-1));
+                    }
+                }
+                Action::Fit => cell_size = None,
+                Action::Hint if !app.state().is_over() => app.hint()?,
+                Action::Help => show_help = true,
+                Action::NewGame => {
+                    self.render = render;
+                    return Ok(true);
+                }
+                Action::Quit => break,
+                _ => {}
             }
+            app.update_heat_map()?;
+            app.update_hint();
         }
 
-        Ok(())
+        self.render = render;
+        Ok(false)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        sweep::{Generator, Settings},
+        topology::Topology,
+    };
+
+    #[test]
+    fn failing_to_generate_a_board_lets_the_player_try_again() {
+        // whichever side of the middle tile the mine is on, finding it takes a guess
+        let settings = Settings::builder()
+            .rows(1)
+            .columns(3)
+            .mines(1)
+            .seed(0)
+            .generator(Generator::NoGuess { attempts: 2 })
+            .build();
+        let mut app = App::new(Board::new(settings).unwrap(), Theme::Dark, Glyphs::Ascii);
+        app.active_column = 1;
+
+        assert_eq!(app.expose_active_cell().unwrap(), GameState::NotStarted);
+        let status = app.status.clone().unwrap();
+        assert!(status.contains("without guessing"), "{status}");
+        assert!(!app.board.tile((0, 0, 1)).unwrap().exposed);
+    }
+
+    #[test]
+    fn saving_reports_what_happened() {
+        let settings = Settings::builder()
+            .rows(4)
+            .columns(4)
+            .mines(2)
+            .seed(1)
+            .build();
+        let mut app = App::new(Board::new(settings).unwrap(), Theme::Dark, Glyphs::Ascii);
+        let path = std::env::temp_dir().join(format!("minesweep-save-{}.txt", std::process::id()));
+
+        app.save_board(&path).unwrap();
+        assert_eq!(app.status.as_deref(), Some("expose a tile first"));
+        assert!(!path.exists());
+
+        app.board.expose((0, 0, 0)).unwrap();
+        app.save_board(&path).unwrap();
+        assert_eq!(app.status, Some(format!("saved to {}", path.display())));
+        let layout = std::fs::read_to_string(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(layout, app.board.to_layout().unwrap());
+    }
+
+    #[test]
+    fn hints_before_the_first_expose_say_any_tile_is_safe() {
+        let settings = Settings::builder()
+            .rows(4)
+            .columns(4)
+            .mines(3)
+            .seed(0)
+            .build();
+        let mut app = App::new(Board::new(settings).unwrap(), Theme::Dark, Glyphs::Ascii);
+        app.hint().unwrap();
+        assert_eq!(app.status.as_deref(), Some("hint: any first tile is safe"));
+        assert!(app.hint.is_none());
+    }
+
+    #[test]
+    fn guessing_hints_ignore_wrong_flags() {
+        // the number can't tell which side its mine is on, and the flag is on the wrong one
+        let board = Board::from_layout("*of\n", Topology::default()).unwrap();
+        let mut app = App::new(board, Theme::Dark, Glyphs::Ascii);
+        app.hint().unwrap();
+        let hint = app.hint.as_ref().unwrap();
+        assert_eq!(hint.message, "hint: no sure move, 50% risk");
+    }
+
+    #[test]
+    fn renderers_cycle_through_every_mode() {
+        let mut render = Render::Blocks;
+        let mut seen = Vec::new();
+        for _ in 0..4 {
+            seen.push(render);
+            assert_eq!(render.to_string().parse(), Ok(render));
+            render = render.next();
+        }
+        assert_eq!(render, Render::Blocks);
+        assert_eq!(
+            seen,
+            [
+                Render::Blocks,
+                Render::Grid,
+                Render::Compact,
+                Render::HalfBlock
+            ]
+        );
+    }
+
+    #[test]
+    fn geometry_measures_what_each_renderer_draws() {
+        // shared borders save a column and a row per tile, closed off by one more at the end
+        let grid = Render::Grid.geometry(5, 3, false);
+        assert_eq!((grid.width(10), grid.height(10)), (41, 21));
+        assert_eq!((grid.columns_in(41), grid.rows_in(21)), (10, 10));
+        assert_eq!((grid.columns_in(40), grid.rows_in(20)), (9, 9));
+
+        let blocks = Render::Blocks.geometry(5, 3, false);
+        assert_eq!((blocks.width(10), blocks.height(10)), (50, 30));
+
+        // two rows share each line, the last one alone if there's an odd number of rows
+        let half = Render::HalfBlock.geometry(5, 3, false);
+        assert_eq!((half.width(10), half.height(5)), (10, 3));
+        assert_eq!(half.rows_in(3), 6);
+
+        // single characters are spaced out on hex grids, and odd rows shifted by one
+        let compact = Render::Compact.geometry(5, 3, true);
+        assert_eq!(compact.width(10), 21);
+        assert_eq!(compact.columns_in(21), 10);
+    }
+
+    #[test]
+    fn zooming_keeps_cells_big_enough_to_draw() {
+        assert_eq!(
+            CellSize::DEFAULT.zoom(1),
+            CellSize {
+                width: 7,
+                height: 4
+            }
+        );
+        assert_eq!(CellSize::DEFAULT.zoom(2).zoom(-2), CellSize::DEFAULT);
+        // the height is already as small as it goes, so only the width shrinks
+        assert_eq!(CellSize::DEFAULT.zoom(-1), CellSize::MIN);
+        assert_eq!(CellSize::MIN.zoom(-5), CellSize::MIN);
+    }
+
+    #[test]
+    fn releasing_buttons_acts_on_everything_pressed() {
+        let release = |pressed: &[MouseButton]| {
+            let mut buttons = Buttons::default();
+            for &button in pressed {
+                buttons.press(button);
+            }
+            buttons.release()
+        };
+        assert_eq!(release(&[MouseButton::Left]), Some(Action::Expose));
+        assert_eq!(release(&[MouseButton::Right]), Some(Action::Flag));
+        assert_eq!(
+            release(&[MouseButton::Middle]),
+            Some(Action::ExposeNeighbours)
+        );
+        assert_eq!(
+            release(&[MouseButton::Right, MouseButton::Left]),
+            Some(Action::ExposeNeighbours)
+        );
+        assert_eq!(release(&[MouseButton::WheelUp]), None);
+        assert_eq!(release(&[]), None);
+
+        // the second button of a chord is released after the buttons were forgotten
+        let mut buttons = Buttons::default();
+        buttons.press(MouseButton::Left);
+        buttons.press(MouseButton::Right);
+        assert_eq!(buttons.release(), Some(Action::ExposeNeighbours));
+        assert_eq!(buttons.release(), None);
+    }
+
+    #[test]
+    fn tiles_are_found_where_they_were_drawn() {
+        let viewport = Viewport {
+            top: 2,
+            left: 3,
+            rows: 4,
+            columns: 5,
+        };
+        let blocks = Render::Blocks.geometry(5, 3, false);
+        assert_eq!(blocks.tile_at((0, 0), &viewport, false), Some((2, 3)));
+        assert_eq!(blocks.tile_at((14, 8), &viewport, false), Some((4, 5)));
+        assert_eq!(blocks.tile_at((24, 11), &viewport, false), Some((5, 7)));
+        assert_eq!(blocks.tile_at((25, 0), &viewport, false), None);
+        assert_eq!(blocks.tile_at((0, 12), &viewport, false), None);
+
+        // odd rows are shifted right by half a cell on hex grids
+        let hex = Render::Blocks.geometry(5, 3, true);
+        assert_eq!(hex.tile_at((2, 3), &viewport, true), Some((3, 3)));
+        let shifted = Viewport { top: 1, ..viewport };
+        assert_eq!(hex.tile_at((1, 0), &shifted, true), None);
+        assert_eq!(hex.tile_at((2, 0), &shifted, true), Some((1, 3)));
+
+        // a line of half blocks is taken as its top row
+        let half = Render::HalfBlock.geometry(5, 3, false);
+        assert_eq!(half.tile_at((0, 1), &viewport, false), Some((4, 3)));
     }
 }
@@ -0,0 +1,96 @@
+/// The window of rows and columns of a layer that fits on screen.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct Viewport {
+    /// The first visible row.
+    pub(crate) top: usize,
+    /// The first visible column.
+    pub(crate) left: usize,
+    /// The number of visible rows.
+    pub(crate) rows: usize,
+    /// The number of visible columns.
+    pub(crate) columns: usize,
+}
+
+/// Scroll a window of `len` positions starting at `start` over `0..total` as little as possible
+/// to keep `margin` positions either side of `position` in view.
+fn scroll(start: usize, len: usize, total: usize, position: usize, margin: usize) -> usize {
+    // a margin of more than half the window would leave nowhere for the position to be
+    let margin = margin.min(len.saturating_sub(1) / 2);
+    start
+        .min(position.saturating_sub(margin))
+        .max((position + margin + 1).saturating_sub(len))
+        .min(total.saturating_sub(len))
+}
+
+impl Viewport {
+    /// Resize the viewport to `rows` by `columns` tiles of a `total_rows` by `total_columns`
+    /// layer, scrolling it as little as possible to keep `margin` tiles between `(row, column)`
+    /// and its edges.
+    pub(crate) fn follow(
+        &mut self,
+        (row, column): (usize, usize),
+        (rows, columns): (usize, usize),
+        (total_rows, total_columns): (usize, usize),
+        margin: usize,
+    ) {
+        self.rows = rows.min(total_rows);
+        self.columns = columns.min(total_columns);
+        self.top = scroll(self.top, self.rows, total_rows, row, margin);
+        self.left = scroll(self.left, self.columns, total_columns, column, margin);
+    }
+
+    /// The visible rows.
+    pub(crate) fn row_range(&self) -> std::ops::Range<usize> {
+        self.top..self.top + self.rows
+    }
+
+    /// The visible columns.
+    pub(crate) fn column_range(&self) -> std::ops::Range<usize> {
+        self.left..self.left + self.columns
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn small_boards_fit_whole() {
+        let mut viewport = Viewport::default();
+        viewport.follow((3, 4), (20, 30), (9, 9), 2);
+        assert_eq!(viewport.row_range(), 0..9);
+        assert_eq!(viewport.column_range(), 0..9);
+    }
+
+    #[test]
+    fn scrolls_only_to_keep_the_margin() {
+        let mut viewport = Viewport::default();
+        viewport.follow((0, 0), (10, 10), (100, 100), 2);
+        assert_eq!((viewport.top, viewport.left), (0, 0));
+
+        // inside the margin, nothing moves
+        viewport.follow((7, 7), (10, 10), (100, 100), 2);
+        assert_eq!((viewport.top, viewport.left), (0, 0));
+
+        viewport.follow((8, 50), (10, 10), (100, 100), 2);
+        assert_eq!((viewport.top, viewport.left), (1, 43));
+
+        // scrolling back up stops the margin short of the cursor
+        viewport.follow((3, 50), (10, 10), (100, 100), 2);
+        assert_eq!(viewport.top, 1);
+        viewport.follow((2, 50), (10, 10), (100, 100), 2);
+        assert_eq!(viewport.top, 0);
+    }
+
+    #[test]
+    fn stops_at_the_far_edges() {
+        let mut viewport = Viewport::default();
+        viewport.follow((99, 99), (10, 10), (100, 100), 2);
+        assert_eq!(viewport.row_range(), 90..100);
+
+        // a margin wider than half the window still keeps the cursor in view
+        viewport.follow((50, 50), (3, 3), (100, 100), 5);
+        assert!(viewport.row_range().contains(&50));
+        assert!(viewport.column_range().contains(&50));
+    }
+}